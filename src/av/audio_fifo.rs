@@ -0,0 +1,105 @@
+use std::ffi::c_void;
+
+use ffmpeg_dev::sys;
+
+use super::resampler::Resampler;
+use super::utils;
+
+// buffers converted samples so they can be handed to an encoder in frame_size chunks
+pub struct AudioFifo {
+    pub fifo: *mut sys::AVAudioFifo,
+    pub format: sys::AVSampleFormat,
+    pub channel_layout: i64,
+    pub sample_rate: i32,
+}
+
+impl AudioFifo {
+    // a fifo holding samples in the encoder's format
+    pub unsafe fn new(codec_ctx: *const sys::AVCodecContext) -> AudioFifo {
+        let format = (*codec_ctx).sample_fmt;
+        let channel_layout = utils::channel_layout(codec_ctx);
+        let channels = sys::av_get_channel_layout_nb_channels(channel_layout as u64);
+
+        let fifo = sys::av_audio_fifo_alloc(format, channels, 1);
+        assert!(!fifo.is_null(), "failed to allocate audio fifo");
+
+        AudioFifo {
+            fifo,
+            format,
+            channel_layout,
+            sample_rate: (*codec_ctx).sample_rate,
+        }
+    }
+
+    pub unsafe fn len(&self) -> i32 {
+        sys::av_audio_fifo_size(self.fifo)
+    }
+
+    pub unsafe fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // converts a decoded frame and queues the result
+    // a null frame flushes the samples buffered inside the resampler
+    pub unsafe fn write(&mut self, resampler: &mut Resampler, frame: *const sys::AVFrame) {
+        let in_samples = if frame.is_null() {
+            0
+        } else {
+            (*frame).nb_samples
+        };
+        let out_samples = resampler.out_samples(in_samples);
+
+        if out_samples <= 0 {
+            return;
+        }
+
+        let mut converted = self.alloc_frame(out_samples);
+        let count = resampler.convert(frame, (*converted).extended_data, out_samples);
+
+        sys::av_audio_fifo_write(
+            self.fifo,
+            (*converted).extended_data as *mut *mut c_void,
+            count,
+        );
+
+        sys::av_frame_free(&mut converted);
+    }
+
+    // dequeues up to `samples` samples into a new frame, without timestamps
+    pub unsafe fn read(&mut self, samples: i32) -> *mut sys::AVFrame {
+        let frame = self.alloc_frame(samples.min(self.len()));
+
+        let count = sys::av_audio_fifo_read(
+            self.fifo,
+            (*frame).extended_data as *mut *mut c_void,
+            (*frame).nb_samples,
+        );
+
+        (*frame).nb_samples = count;
+
+        frame
+    }
+
+    unsafe fn alloc_frame(&self, samples: i32) -> *mut sys::AVFrame {
+        let frame = sys::av_frame_alloc();
+
+        (*frame).nb_samples = samples;
+        (*frame).format = self.format;
+        (*frame).channel_layout = self.channel_layout as u64;
+        (*frame).sample_rate = self.sample_rate;
+
+        let response = sys::av_frame_get_buffer(frame, 0);
+        assert!(
+            !utils::check_error(response),
+            "failed to allocate audio frame"
+        );
+
+        frame
+    }
+}
+
+impl Drop for AudioFifo {
+    fn drop(&mut self) {
+        unsafe { sys::av_audio_fifo_free(self.fifo) }
+    }
+}
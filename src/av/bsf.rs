@@ -0,0 +1,131 @@
+use std::ptr::null_mut;
+use std::str::FromStr;
+
+use ffmpeg_dev::extra::defs::{averror, averror_eof, eagain};
+use ffmpeg_dev::sys;
+
+use super::output_ctx::OutputCtx;
+use super::utils;
+
+// one `--bsf` entry, e.g. `0=h264_mp4toannexb` or `1=none` to disable the automatic filter
+pub struct BsfSpec {
+    pub stream: usize,
+    pub filter: String,
+}
+
+impl FromStr for BsfSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+
+        let stream = parts
+            .next()
+            .and_then(|stream| stream.parse().ok())
+            .ok_or_else(|| format!("invalid bsf stream in '{}'", s))?;
+
+        let filter = match parts.next() {
+            Some(filter) if !filter.is_empty() => filter.to_string(),
+            _ => return Err(format!("missing bsf filter in '{}'", s)),
+        };
+
+        Ok(BsfSpec { stream, filter })
+    }
+}
+
+pub struct Bsf {
+    pub ctx: *mut sys::AVBSFContext,
+}
+
+impl Bsf {
+    // `filters` is a bsf chain as in ffmpeg's -bsf, e.g. `h264_mp4toannexb,dump_extra`
+    pub unsafe fn new(filters: &str, stream: *mut sys::AVStream) -> Bsf {
+        let filters_str = utils::str_to_c_str(filters);
+        let mut ctx = null_mut();
+
+        let response = sys::av_bsf_list_parse_str(filters_str.as_ptr(), &mut ctx);
+        assert!(
+            !utils::check_error(response),
+            "invalid bitstream filter {}",
+            filters
+        );
+
+        sys::avcodec_parameters_copy((*ctx).par_in, (*stream).codecpar);
+        (*ctx).time_base_in = (*stream).time_base;
+
+        let response = sys::av_bsf_init(ctx);
+        assert!(
+            !utils::check_error(response),
+            "failed to initialize bitstream filter {} for stream #{}",
+            filters,
+            (*stream).index
+        );
+
+        Bsf { ctx }
+    }
+
+    pub unsafe fn output_params(&self) -> *mut sys::AVCodecParameters {
+        (*self.ctx).par_out
+    }
+
+    pub unsafe fn time_base(&self) -> sys::AVRational {
+        (*self.ctx).time_base_out
+    }
+
+    // takes ownership of the packet's data; a null packet starts draining the filter
+    pub unsafe fn send_packet(&mut self, packet: *mut sys::AVPacket) -> i32 {
+        let response = sys::av_bsf_send_packet(self.ctx, packet);
+
+        utils::check_error(response);
+
+        response
+    }
+
+    // returns false when the filter needs more input or is fully drained
+    pub unsafe fn receive_packet(&mut self, packet: *mut sys::AVPacket) -> bool {
+        let response = sys::av_bsf_receive_packet(self.ctx, packet);
+
+        if response == averror(eagain()) || response == averror_eof() {
+            false
+        } else {
+            !utils::check_error(response)
+        }
+    }
+}
+
+impl Drop for Bsf {
+    fn drop(&mut self) {
+        unsafe { sys::av_bsf_free(&mut self.ctx) }
+    }
+}
+
+// the filter a stream needs when stream-copied into `output`, if any
+pub unsafe fn auto_filter(
+    codec_params: *const sys::AVCodecParameters,
+    output: &OutputCtx,
+) -> Option<&'static str> {
+    let format = utils::c_str_to_string((*(*output.av).oformat).name);
+    let global_header = (*(*output.av).oformat).flags & sys::AVFMT_GLOBALHEADER as i32 != 0;
+
+    // annex b muxers want start codes in-band, not the avcC/hvcC length prefixes of mp4/mkv
+    let annexb = ["mpegts", "h264", "hevc"].contains(&format.as_str());
+
+    match (*codec_params).codec_id {
+        sys::AVCodecID_AV_CODEC_ID_H264 if annexb && length_prefixed(codec_params) => {
+            Some("h264_mp4toannexb")
+        }
+        sys::AVCodecID_AV_CODEC_ID_HEVC if annexb && length_prefixed(codec_params) => {
+            Some("hevc_mp4toannexb")
+        }
+        // adts aac (from ts or raw .aac) comes without an AudioSpecificConfig
+        sys::AVCodecID_AV_CODEC_ID_AAC if global_header && (*codec_params).extradata_size == 0 => {
+            Some("aac_adtstoasc")
+        }
+        _ => None,
+    }
+}
+
+// avcC/hvcC extradata starts with a version byte of 1, annex b with a start code
+pub unsafe fn length_prefixed(codec_params: *const sys::AVCodecParameters) -> bool {
+    (*codec_params).extradata_size > 0 && *(*codec_params).extradata == 1
+}
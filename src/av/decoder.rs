@@ -0,0 +1,64 @@
+use std::ptr::null_mut;
+
+use ffmpeg_dev::extra::defs::{averror, averror_eof, eagain};
+use ffmpeg_dev::sys;
+
+use super::utils;
+
+pub struct Decoder {
+    pub codec_ctx: *mut sys::AVCodecContext,
+}
+
+impl Decoder {
+    pub unsafe fn new(stream: *mut sys::AVStream) -> Decoder {
+        let codec_params = (*stream).codecpar;
+
+        // find codec
+        let codec = sys::avcodec_find_decoder((*codec_params).codec_id);
+        assert!(
+            codec != null_mut(),
+            "no decoder found for stream #{}",
+            (*stream).index
+        );
+
+        // alloc ctx for codec and set codec params
+        let codec_ctx = sys::avcodec_alloc_context3(codec);
+        sys::avcodec_parameters_to_context(codec_ctx, codec_params);
+        (*codec_ctx).pkt_timebase = (*stream).time_base;
+
+        // open stream
+        let response = sys::avcodec_open2(codec_ctx, codec, null_mut());
+        assert!(!utils::check_error(response), "failed to open decoder");
+
+        Decoder { codec_ctx }
+    }
+
+    // a null packet starts draining the decoder
+    pub unsafe fn send_packet(&mut self, packet: *const sys::AVPacket) -> i32 {
+        let response = sys::avcodec_send_packet(self.codec_ctx, packet);
+
+        // eof -> decoder was already drained, not an actual error here
+        if response != averror_eof() {
+            utils::check_error(response);
+        }
+
+        response
+    }
+
+    // returns false when the decoder needs more input or is fully drained
+    pub unsafe fn receive_frame(&mut self, frame: *mut sys::AVFrame) -> bool {
+        let response = sys::avcodec_receive_frame(self.codec_ctx, frame);
+
+        if response == averror(eagain()) || response == averror_eof() {
+            false
+        } else {
+            !utils::check_error(response)
+        }
+    }
+}
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        unsafe { sys::avcodec_free_context(&mut self.codec_ctx) }
+    }
+}
@@ -0,0 +1,160 @@
+use std::ffi::c_void;
+use std::ptr::null_mut;
+
+use ffmpeg_dev::extra::defs::{averror, averror_eof, eagain};
+use ffmpeg_dev::sys;
+
+use super::output_ctx::OutputCtx;
+use super::utils;
+
+pub struct Encoder {
+    pub codec: *mut sys::AVCodec,
+    pub codec_ctx: *mut sys::AVCodecContext,
+}
+
+impl Encoder {
+    // only allocates the context, parameters must be set before calling `open`
+    pub unsafe fn new(name: &str) -> Encoder {
+        let name_str = utils::str_to_c_str(name);
+
+        let codec = sys::avcodec_find_encoder_by_name(name_str.as_ptr());
+        assert!(codec != null_mut(), "encoder {} not found", name);
+
+        Encoder {
+            codec,
+            codec_ctx: sys::avcodec_alloc_context3(codec),
+        }
+    }
+
+    // default encoder for a codec id, e.g. libx264 for h264 when available
+    pub unsafe fn for_codec(codec_id: sys::AVCodecID) -> Encoder {
+        let codec = sys::avcodec_find_encoder(codec_id);
+        assert!(
+            codec != null_mut(),
+            "no encoder found for {}",
+            utils::c_str_to_string(sys::avcodec_get_name(codec_id))
+        );
+
+        Encoder {
+            codec,
+            codec_ctx: sys::avcodec_alloc_context3(codec),
+        }
+    }
+
+    // first pixel format supported by the encoder
+    pub unsafe fn default_pix_fmt(&self) -> sys::AVPixelFormat {
+        assert!(
+            (*self.codec).pix_fmts != null_mut(),
+            "encoder does not declare its pixel formats"
+        );
+
+        *(*self.codec).pix_fmts
+    }
+
+    // first sample format supported by the encoder
+    pub unsafe fn default_sample_fmt(&self) -> sys::AVSampleFormat {
+        assert!(
+            (*self.codec).sample_fmts != null_mut(),
+            "encoder does not declare its sample formats"
+        );
+
+        *(*self.codec).sample_fmts
+    }
+
+    // sets a codec context option (e.g. b, g, maxrate) or a private one of the encoder
+    // (e.g. preset, crf, x265-params), panicking with the reason when it is rejected
+    pub unsafe fn set_option(&mut self, key: &str, value: &str) {
+        let key_str = utils::str_to_c_str(key);
+        let value_str = utils::str_to_c_str(value);
+        let name = utils::c_str_to_string((*self.codec).name);
+
+        let response = sys::av_opt_set(
+            self.codec_ctx as *mut c_void,
+            key_str.as_ptr(),
+            value_str.as_ptr(),
+            sys::AV_OPT_SEARCH_CHILDREN as i32,
+        );
+
+        if response == utils::AVERROR_OPTION_NOT_FOUND {
+            panic!("encoder {} has no option '{}'", name, key);
+        }
+
+        if response < 0 {
+            panic!(
+                "invalid value '{}' for option '{}' of encoder {}: {}",
+                value,
+                key,
+                name,
+                utils::averror_to_str(response)
+            );
+        }
+    }
+
+    pub unsafe fn open(&mut self, output: &OutputCtx) {
+        // some containers (e.g. mp4) want codec headers in extradata rather than in-band
+        if (*(*output.av).oformat).flags & sys::AVFMT_GLOBALHEADER as i32 > 0 {
+            (*self.codec_ctx).flags |= sys::AV_CODEC_FLAG_GLOBAL_HEADER as i32;
+        }
+
+        self.open_in_band();
+    }
+
+    // opens the encoder with its codec headers in the bitstream, whatever the container
+    pub unsafe fn open_in_band(&mut self) {
+        let response = sys::avcodec_open2(self.codec_ctx, self.codec, null_mut());
+        assert!(!utils::check_error(response), "failed to open encoder");
+    }
+
+    // creates an output stream matching the (opened) encoder
+    pub unsafe fn new_stream(&self, output: &mut OutputCtx) -> *mut sys::AVStream {
+        let out_stream = sys::avformat_new_stream(output.av, null_mut());
+        assert!(out_stream != null_mut(), "failed to allocate output stream");
+
+        sys::avcodec_parameters_from_context((*out_stream).codecpar, self.codec_ctx);
+        (*out_stream).time_base = (*self.codec_ctx).time_base;
+
+        out_stream
+    }
+
+    // a null frame starts draining the encoder
+    pub unsafe fn send_frame(&mut self, frame: *const sys::AVFrame) -> i32 {
+        let response = sys::avcodec_send_frame(self.codec_ctx, frame);
+
+        // eof -> encoder was already drained, not an actual error here
+        if response != averror_eof() {
+            utils::check_error(response);
+        }
+
+        response
+    }
+
+    // returns false when the encoder needs more input or is fully drained
+    pub unsafe fn receive_packet(&mut self, packet: *mut sys::AVPacket) -> bool {
+        let response = sys::avcodec_receive_packet(self.codec_ctx, packet);
+
+        if response == averror(eagain()) || response == averror_eof() {
+            false
+        } else {
+            !utils::check_error(response)
+        }
+    }
+
+    // writes every packet the encoder has ready to the given output stream
+    pub unsafe fn write_packets(&mut self, output: &mut OutputCtx, stream_index: i32) {
+        let mut packet = sys::av_packet_alloc();
+
+        while self.receive_packet(packet) {
+            (*packet).stream_index = stream_index;
+            output.write_packet(packet, (*self.codec_ctx).time_base);
+            sys::av_packet_unref(packet);
+        }
+
+        sys::av_packet_free(&mut packet);
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        unsafe { sys::avcodec_free_context(&mut self.codec_ctx) }
+    }
+}
@@ -0,0 +1,248 @@
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::ptr::null_mut;
+use std::slice;
+
+use ffmpeg_dev::extra::defs::{averror, averror_eof, eagain};
+use ffmpeg_dev::sys;
+
+use super::scaler::Scaler;
+use super::utils;
+
+const GLYPH_WIDTH: i32 = 5;
+const GLYPH_HEIGHT: i32 = 7;
+
+// packed RGB24 canvas
+pub struct Image {
+    pub width: i32,
+    pub height: i32,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: i32, height: i32, color: [u8; 3]) -> Image {
+        let mut data = Vec::with_capacity((width * height * 3) as usize);
+
+        for _ in 0..(width * height) {
+            data.extend_from_slice(&color);
+        }
+
+        Image {
+            width,
+            height,
+            data,
+        }
+    }
+
+    // copies an RGB24 frame into a new image
+    pub unsafe fn from_frame(frame: *const sys::AVFrame) -> Image {
+        assert_eq!(
+            (*frame).format,
+            sys::AVPixelFormat_AV_PIX_FMT_RGB24,
+            "frame is not RGB24"
+        );
+
+        let width = (*frame).width;
+        let height = (*frame).height;
+        let linesize = (*frame).linesize[0];
+        let pixels = slice::from_raw_parts((*frame).data[0], (linesize * height) as usize);
+
+        let mut data = Vec::with_capacity((width * height * 3) as usize);
+
+        for y in 0..height {
+            let start = (linesize * y) as usize;
+            let end = start + (width * 3) as usize;
+
+            data.extend_from_slice(&pixels[start..end]);
+        }
+
+        Image {
+            width,
+            height,
+            data,
+        }
+    }
+
+    // allocates an RGB24 frame holding a copy of this image
+    pub unsafe fn to_frame(&self) -> *mut sys::AVFrame {
+        let frame =
+            utils::alloc_frame(self.width, self.height, sys::AVPixelFormat_AV_PIX_FMT_RGB24);
+
+        let linesize = (*frame).linesize[0];
+        let pixels = slice::from_raw_parts_mut((*frame).data[0], (linesize * self.height) as usize);
+        let row = (self.width * 3) as usize;
+
+        for y in 0..self.height as usize {
+            let start = linesize as usize * y;
+
+            pixels[start..start + row].copy_from_slice(&self.data[row * y..row * (y + 1)]);
+        }
+
+        frame
+    }
+
+    pub fn put_pixel(&mut self, x: i32, y: i32, color: [u8; 3]) {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+
+        let offset = ((y * self.width + x) * 3) as usize;
+        self.data[offset..offset + 3].copy_from_slice(&color);
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: [u8; 3]) {
+        for j in y..(y + height) {
+            for i in x..(x + width) {
+                self.put_pixel(i, j, color);
+            }
+        }
+    }
+
+    // pastes `other` with its top-left corner at (x, y)
+    pub fn blit(&mut self, other: &Image, x: i32, y: i32) {
+        for j in 0..other.height {
+            for i in 0..other.width {
+                let offset = ((j * other.width + i) * 3) as usize;
+                let color = [
+                    other.data[offset],
+                    other.data[offset + 1],
+                    other.data[offset + 2],
+                ];
+
+                self.put_pixel(x + i, y + j, color);
+            }
+        }
+    }
+
+    // size in pixels of `text` when drawn with `draw_text`
+    pub fn text_size(text: &str, scale: i32) -> (i32, i32) {
+        let count = text.chars().count() as i32;
+
+        if count == 0 {
+            return (0, 0);
+        }
+
+        (
+            (count * (GLYPH_WIDTH + 1) - 1) * scale,
+            GLYPH_HEIGHT * scale,
+        )
+    }
+
+    // draws text using a built-in bitmap font
+    // only digits, ':', '.', '-' and spaces are supported, which is enough for timestamps
+    pub fn draw_text(&mut self, text: &str, x: i32, y: i32, scale: i32, color: [u8; 3]) {
+        for (n, c) in text.chars().enumerate() {
+            let glyph = glyph(c);
+            let origin = x + n as i32 * (GLYPH_WIDTH + 1) * scale;
+
+            for (row, bits) in glyph.iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                        continue;
+                    }
+
+                    self.fill_rect(
+                        origin + col * scale,
+                        y + row as i32 * scale,
+                        scale,
+                        scale,
+                        color,
+                    );
+                }
+            }
+        }
+    }
+
+    // encodes the image with the codec matching the file extension (png, jpg/jpeg or ppm)
+    pub unsafe fn save(&self, path: &str) {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        let (codec_id, pix_fmt) = match extension.as_str() {
+            "png" => (
+                sys::AVCodecID_AV_CODEC_ID_PNG,
+                sys::AVPixelFormat_AV_PIX_FMT_RGB24,
+            ),
+            "jpg" | "jpeg" => (
+                sys::AVCodecID_AV_CODEC_ID_MJPEG,
+                sys::AVPixelFormat_AV_PIX_FMT_YUVJ420P,
+            ),
+            "ppm" => (
+                sys::AVCodecID_AV_CODEC_ID_PPM,
+                sys::AVPixelFormat_AV_PIX_FMT_RGB24,
+            ),
+            x => panic!("unsupported image extension '{}'", x),
+        };
+
+        let codec = sys::avcodec_find_encoder(codec_id);
+        assert!(codec != null_mut(), "no encoder found for {}", path);
+
+        let mut codec_ctx = sys::avcodec_alloc_context3(codec);
+        (*codec_ctx).width = self.width;
+        (*codec_ctx).height = self.height;
+        (*codec_ctx).pix_fmt = pix_fmt;
+        (*codec_ctx).time_base = sys::AVRational { num: 1, den: 25 };
+        (*codec_ctx).qmin = 1;
+        (*codec_ctx).qmax = 3;
+
+        let response = sys::avcodec_open2(codec_ctx, codec, null_mut());
+        assert!(
+            !utils::check_error(response),
+            "failed to open image encoder"
+        );
+
+        let mut rgb = self.to_frame();
+        let mut frame = utils::alloc_frame(self.width, self.height, pix_fmt);
+        Scaler::new().scale(rgb, frame);
+
+        let mut packet = sys::av_packet_alloc();
+        let mut file = File::create(path).unwrap();
+
+        sys::avcodec_send_frame(codec_ctx, frame);
+        sys::avcodec_send_frame(codec_ctx, null_mut());
+
+        loop {
+            let response = sys::avcodec_receive_packet(codec_ctx, packet);
+
+            if response == averror(eagain()) || response == averror_eof() {
+                break;
+            } else if utils::check_error(response) {
+                break;
+            }
+
+            let data = slice::from_raw_parts((*packet).data, (*packet).size as usize);
+            file.write_all(data).unwrap();
+
+            sys::av_packet_unref(packet);
+        }
+
+        sys::av_packet_free(&mut packet);
+        sys::av_frame_free(&mut frame);
+        sys::av_frame_free(&mut rgb);
+        sys::avcodec_free_context(&mut codec_ctx);
+    }
+}
+
+// 5x7 glyphs, one byte per row, most significant of the low 5 bits is the leftmost pixel
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        ':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+        '-' => [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00],
+        _ => [0x00; 7],
+    }
+}
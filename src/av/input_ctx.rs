@@ -16,11 +16,34 @@ pub struct InputCtx {
 
 impl InputCtx {
     pub unsafe fn new(path: &str) -> InputCtx {
+        InputCtx::open(path, None, &[])
+    }
+
+    // opens the input with an explicit demuxer and demuxer private options
+    pub unsafe fn open(path: &str, format: Option<&str>, options: &[(String, String)]) -> InputCtx {
         let mut av = sys::avformat_alloc_context();
         let path_str = utils::str_to_c_str(path);
 
+        let input_format = match format {
+            Some(name) => {
+                let name_str = utils::str_to_c_str(name);
+                let input_format = sys::av_find_input_format(name_str.as_ptr());
+
+                assert!(input_format != null_mut(), "unknown input format {}", name);
+
+                input_format
+            }
+            None => null_mut(),
+        };
+
+        let mut dict = utils::dictionary(options);
+
         // open input file
-        sys::avformat_open_input(&mut av, path_str.as_ptr(), null_mut(), null_mut());
+        let response =
+            sys::avformat_open_input(&mut av, path_str.as_ptr(), input_format, &mut dict);
+        assert!(!utils::check_error(response), "could not open {}", path);
+
+        sys::av_dict_free(&mut dict);
 
         // load stream info
         sys::avformat_find_stream_info(av, null_mut());
@@ -50,6 +73,52 @@ impl InputCtx {
         self.get_streams()[i]
     }
 
+    // start and duration of a stream, in its own time base
+    pub unsafe fn stream_range(&self, i: usize) -> (i64, i64) {
+        let stream = self.get_stream(i);
+
+        let start = if (*stream).start_time == utils::AV_NOPTS_VALUE {
+            0
+        } else {
+            (*stream).start_time
+        };
+
+        // stream duration is often missing, fall back to the container one
+        let duration = if (*stream).duration > 0 {
+            (*stream).duration
+        } else {
+            sys::av_rescale_q(
+                (*self.av).duration,
+                utils::av_time_base_q(),
+                (*stream).time_base,
+            )
+        };
+
+        assert!(
+            duration > 0,
+            "could not determine the duration of stream #{}",
+            i
+        );
+
+        (start, duration)
+    }
+
+    // index of the "best" stream of a given type, as picked by libavformat
+    pub unsafe fn find_stream(&self, media_type: sys::AVMediaType) -> Option<i32> {
+        let index = sys::av_find_best_stream(self.av, media_type, -1, -1, null_mut(), 0);
+
+        if index >= 0 {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    pub unsafe fn find_video_stream(&self) -> i32 {
+        self.find_stream(sys::AVMediaType_AVMEDIA_TYPE_VIDEO)
+            .expect("no video stream found")
+    }
+
     pub unsafe fn open_video_stream(&mut self, i: i32) {
         let stream = self.get_stream(i as usize);
 
@@ -78,44 +147,52 @@ impl InputCtx {
         self.codec = codec_ctx;
     }
 
-    pub unsafe fn read_video_frame(&mut self) {
-        while sys::av_read_frame(self.av, self.packet) >= 0 {
-            if (*self.packet).stream_index == self.video_stream_index {
-                let response = self.decode_packet();
+    // seeks the open video stream to the keyframe at or before `timestamp` (in stream time base)
+    pub unsafe fn seek(&mut self, timestamp: i64) {
+        let response = sys::av_seek_frame(
+            self.av,
+            self.video_stream_index,
+            timestamp,
+            sys::AVSEEK_FLAG_BACKWARD as i32,
+        );
 
-                if response < 0 {
-                    break;
-                }
-            }
-        }
+        utils::check_error(response);
+
+        sys::avcodec_flush_buffers(self.codec);
     }
 
-    unsafe fn decode_packet(&mut self) -> i32 {
-        let mut response;
+    // returns false once the input runs out of frames
+    pub unsafe fn read_video_frame(&mut self) -> bool {
+        loop {
+            let response = sys::avcodec_receive_frame(self.codec, self.frame);
 
-        // decode packet
-        response = sys::avcodec_send_packet(self.codec, self.packet);
+            // eagain -> decoder needs another packet
+            // eof -> input is over, not an actual error here
+            if response >= 0 {
+                return true;
+            } else if response == averror_eof() {
+                return false;
+            } else if response != averror(eagain()) {
+                utils::check_error(response);
+                return false;
+            }
 
-        if utils::check_error(response) {
-            return response;
-        }
+            if sys::av_read_frame(self.av, self.packet) < 0 {
+                // no more packets, drain frames still buffered in the decoder
+                sys::avcodec_send_packet(self.codec, null_mut());
+                continue;
+            }
 
-        while response >= 0 {
-            response = sys::avcodec_receive_frame(self.codec, self.frame);
+            if (*self.packet).stream_index == self.video_stream_index {
+                let response = sys::avcodec_send_packet(self.codec, self.packet);
 
-            // eagain -> need to try again
-            // eof -> input is over, not an actual error here
-            if response == averror(eagain()) || response == averror(averror_eof()) {
-                break;
-            } else if utils::check_error(response) {
-                return response;
+                if utils::check_error(response) {
+                    sys::av_packet_unref(self.packet);
+                    return false;
+                }
             }
 
-            if response >= 0 {
-                return -1;
-            }
+            sys::av_packet_unref(self.packet);
         }
-
-        0
     }
 }
@@ -3,5 +3,21 @@ extern crate ffmpeg_dev;
 pub(super) mod debug;
 pub(super) mod utils;
 
+pub mod audio_fifo;
+pub mod bsf;
+pub mod decoder;
+pub mod encoder;
+pub mod image;
 pub mod input_ctx;
+pub mod mp4;
 pub mod output_ctx;
+pub mod preflight;
+pub mod progress;
+pub mod resampler;
+pub mod scaler;
+pub mod stream_map;
+pub mod subtitle;
+#[cfg(test)]
+pub mod testing;
+pub mod timestamps;
+pub mod transcoder;
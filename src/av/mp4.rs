@@ -0,0 +1,257 @@
+use std::str::FromStr;
+
+// muxer layouts for mp4/mov outputs, as combinations of the mov muxer movflags
+pub enum Mp4Mode {
+    // moov before mdat, so playback can start before the whole file is downloaded
+    Faststart,
+    // moof/mdat fragments starting at each keyframe
+    Fragmented,
+    // fragmented, following the CMAF constraints
+    Cmaf,
+}
+
+impl FromStr for Mp4Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "faststart" => Ok(Mp4Mode::Faststart),
+            "fragmented" => Ok(Mp4Mode::Fragmented),
+            "cmaf" => Ok(Mp4Mode::Cmaf),
+            x => Err(format!("unknown mp4 mode '{}'", x)),
+        }
+    }
+}
+
+// muxer options for an mp4/mov output, none without a mode; `frag_duration` (in microseconds)
+// additionally cuts fragments within long GOPs
+pub fn muxer_options(mode: Option<&Mp4Mode>, frag_duration: Option<i64>) -> Vec<(String, String)> {
+    let fragmented = match mode {
+        Some(Mp4Mode::Fragmented) | Some(Mp4Mode::Cmaf) => true,
+        _ => false,
+    };
+
+    assert!(
+        frag_duration.is_none() || fragmented,
+        "--frag-duration needs --mp4-mode fragmented or cmaf"
+    );
+
+    let movflags = match mode {
+        None => return Vec::new(),
+        Some(Mp4Mode::Faststart) => "+faststart",
+        Some(Mp4Mode::Fragmented) => "+frag_keyframe+empty_moov+default_base_moof",
+        Some(Mp4Mode::Cmaf) => "+cmaf+frag_keyframe+empty_moov+default_base_moof",
+    };
+
+    let mut options = vec![("movflags".to_string(), movflags.to_string())];
+
+    if let Some(duration) = frag_duration {
+        options.push(("frag_duration".to_string(), duration.to_string()));
+    }
+
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::av::testing;
+    use std::convert::TryInto;
+    use std::fs::File;
+    use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+    // types of the top-level boxes of an mp4 file, in order, e.g. ["ftyp", "moov", "mdat"]
+    fn top_level_atoms(path: &str) -> io::Result<Vec<String>> {
+        let mut file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+
+        read_atoms(&mut file, file_size)
+    }
+
+    // same as `top_level_atoms`, over `file_size` bytes of any seekable reader
+    fn read_atoms<R: Read + Seek>(file: &mut R, file_size: u64) -> io::Result<Vec<String>> {
+        let mut atoms = Vec::new();
+        let mut position = 0;
+
+        while position + 8 <= file_size {
+            let mut header = [0u8; 8];
+            file.seek(SeekFrom::Start(position))?;
+            file.read_exact(&mut header)?;
+
+            let size = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+            let name = String::from_utf8_lossy(&header[4..8]).to_string();
+
+            let size = match size {
+                // box extends to the end of the file
+                0 => file_size - position,
+                // 64-bit size follows the type
+                1 => {
+                    let mut large_size = [0u8; 8];
+                    file.read_exact(&mut large_size)?;
+                    u64::from_be_bytes(large_size)
+                }
+                size => size,
+            };
+
+            atoms.push(name);
+
+            if size < 8 {
+                break;
+            }
+
+            position += size;
+        }
+
+        Ok(atoms)
+    }
+
+    // a box of the given type with `payload` zero bytes of content
+    fn atom(name: &str, payload: usize) -> Vec<u8> {
+        let mut data = ((payload + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(name.as_bytes());
+        data.resize(payload + 8, 0);
+        data
+    }
+
+    fn atoms(boxes: &[Vec<u8>]) -> Vec<String> {
+        let data = boxes.concat();
+        let size = data.len() as u64;
+
+        read_atoms(&mut Cursor::new(data), size).unwrap()
+    }
+
+    #[test]
+    fn faststart_order() {
+        let order = atoms(&[atom("ftyp", 16), atom("moov", 100), atom("mdat", 1000)]);
+
+        assert_eq!(order, vec!["ftyp", "moov", "mdat"]);
+    }
+
+    #[test]
+    fn fragmented_order() {
+        let order = atoms(&[
+            atom("ftyp", 16),
+            atom("moov", 100),
+            atom("moof", 40),
+            atom("mdat", 500),
+            atom("moof", 40),
+            atom("mdat", 500),
+            atom("mfra", 20),
+        ]);
+
+        assert_eq!(
+            order,
+            vec!["ftyp", "moov", "moof", "mdat", "moof", "mdat", "mfra"]
+        );
+    }
+
+    #[test]
+    fn large_and_open_ended_sizes() {
+        // 64-bit size right after the type
+        let mut large = vec![0, 0, 0, 1];
+        large.extend_from_slice(b"mdat");
+        large.extend_from_slice(&24u64.to_be_bytes());
+        large.resize(24, 0);
+
+        // size 0 runs to the end of the file
+        let mut open_ended = vec![0, 0, 0, 0];
+        open_ended.extend_from_slice(b"free");
+        open_ended.resize(32, 0);
+
+        let order = atoms(&[atom("ftyp", 16), large, open_ended]);
+
+        assert_eq!(order, vec!["ftyp", "mdat", "free"]);
+    }
+
+    #[test]
+    fn faststart_options() {
+        let options = muxer_options(Some(&Mp4Mode::Faststart), None);
+
+        assert_eq!(
+            options,
+            vec![("movflags".to_string(), "+faststart".to_string())]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "--frag-duration")]
+    fn faststart_rejects_frag_duration() {
+        muxer_options(Some(&Mp4Mode::Faststart), Some(2_000_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "--frag-duration")]
+    fn frag_duration_needs_a_mode() {
+        muxer_options(None, Some(2_000_000));
+    }
+
+    #[test]
+    fn fragmented_options() {
+        let options = muxer_options(Some(&Mp4Mode::Fragmented), Some(2_000_000));
+
+        assert_eq!(
+            options,
+            vec![
+                (
+                    "movflags".to_string(),
+                    "+frag_keyframe+empty_moov+default_base_moof".to_string()
+                ),
+                ("frag_duration".to_string(), "2000000".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn cmaf_options() {
+        let options = muxer_options(Some(&Mp4Mode::Cmaf), None);
+
+        assert_eq!(
+            options,
+            vec![(
+                "movflags".to_string(),
+                "+cmaf+frag_keyframe+empty_moov+default_base_moof".to_string()
+            )]
+        );
+    }
+
+    // top-level atoms of a short h264 file muxed in the given mode
+    fn muxed_atoms(name: &str, mode: Mp4Mode) -> Vec<String> {
+        let path = testing::temp_path(name);
+
+        unsafe { testing::write_video(&path, 50, Some(&mode)) };
+
+        let atoms = top_level_atoms(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        atoms
+    }
+
+    #[test]
+    fn faststart_file() {
+        let order = muxed_atoms("faststart.mp4", Mp4Mode::Faststart);
+        let position = |name: &str| order.iter().position(|atom| atom == name);
+
+        assert_eq!(order.first().map(String::as_str), Some("ftyp"));
+        assert!(position("moov").is_some());
+        assert!(position("moov") < position("mdat"), "{:?}", order);
+    }
+
+    #[test]
+    fn fragmented_file() {
+        let order = muxed_atoms("fragmented.mp4", Mp4Mode::Fragmented);
+        let fragments: Vec<_> = order
+            .iter()
+            .map(String::as_str)
+            .skip(2)
+            .take_while(|&atom| atom != "mfra")
+            .collect();
+
+        assert_eq!(order[..2], ["ftyp", "moov"], "{:?}", order);
+        // a keyframe every 10 frames, so one fragment each
+        assert_eq!(fragments.len(), 2 * 5, "{:?}", order);
+
+        for pair in fragments.chunks(2) {
+            assert_eq!(pair, ["moof", "mdat"], "{:?}", order);
+        }
+    }
+}
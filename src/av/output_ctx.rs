@@ -1,37 +1,116 @@
-use std::ptr::null_mut;
+use std::ptr::{null, null_mut};
 
 use ffmpeg_dev::sys;
 
+use super::mp4::{self, Mp4Mode};
 use super::utils;
 
 pub struct OutputCtx {
     pub av: *mut sys::AVFormatContext,
+    // muxer options set up ahead of `open_file_with_options`, e.g. movflags
+    options: Vec<(String, String)>,
 }
 
 impl OutputCtx {
-    pub unsafe fn new(path: &str) -> OutputCtx {
+    // uses the given muxer instead of guessing it from the file extension
+    pub unsafe fn open(path: &str, format: Option<&str>) -> OutputCtx {
         let path_str = utils::str_to_c_str(path);
+        let format_str = format.map(utils::str_to_c_str);
+        let format_ptr = format_str.as_ref().map_or(null(), |f| f.as_ptr());
 
         let mut av: *mut sys::AVFormatContext = null_mut();
 
-        sys::avformat_alloc_output_context2(&mut av, null_mut(), null_mut(), path_str.as_ptr());
+        let response =
+            sys::avformat_alloc_output_context2(&mut av, null_mut(), format_ptr, path_str.as_ptr());
 
-        OutputCtx { av: av }
+        assert!(
+            !utils::check_error(response),
+            "could not find an output format for {}",
+            path
+        );
+
+        OutputCtx {
+            av: av,
+            options: Vec::new(),
+        }
+    }
+
+    // movflags (and fragment duration) for mp4/mov outputs, applied when the file is opened
+    pub fn set_mp4_mode(&mut self, mode: Option<&Mp4Mode>, frag_duration: Option<i64>) {
+        self.options.extend(mp4::muxer_options(mode, frag_duration));
     }
 
-    pub unsafe fn open_file(&mut self, path: &str) {
+    // options are passed to the muxer, e.g. hls_time or movflags, after the ones set up with
+    // `set_mp4_mode` so they can override them
+    pub unsafe fn open_file_with_options(&mut self, path: &str, options: &[(String, String)]) {
         let path_str = utils::str_to_c_str(path);
 
-        let response = sys::avio_open(
-            &mut (*self.av).pb,
-            path_str.as_ptr(),
-            sys::AVIO_FLAG_WRITE as i32,
-        );
+        // some muxers (e.g. hls, image2) open their own files
+        if (*(*self.av).oformat).flags & sys::AVFMT_NOFILE as i32 == 0 {
+            let response = sys::avio_open(
+                &mut (*self.av).pb,
+                path_str.as_ptr(),
+                sys::AVIO_FLAG_WRITE as i32,
+            );
+
+            utils::check_error(response);
+        }
+
+        let mut all_options = self.options.clone();
+        all_options.extend_from_slice(options);
+
+        let mut dict = utils::dictionary(&all_options);
+
+        let response = sys::avformat_write_header(self.av, &mut dict);
 
         utils::check_error(response);
 
-        let response = sys::avformat_write_header(self.av, null_mut());
+        // whatever is left in the dictionary was not recognized by the muxer
+        let empty = utils::str_to_c_str("");
+        let mut entry = null_mut();
+
+        loop {
+            entry = sys::av_dict_get(
+                dict,
+                empty.as_ptr(),
+                entry,
+                sys::AV_DICT_IGNORE_SUFFIX as i32,
+            );
+
+            if entry == null_mut() {
+                break;
+            }
+
+            println!(
+                "warning: muxer option {} was not used",
+                utils::c_str_to_string((*entry).key)
+            );
+        }
+
+        sys::av_dict_free(&mut dict);
+    }
+
+    // rescales the packet timestamps from `time_base` to its output stream's and writes it
+    pub unsafe fn write_packet(&mut self, packet: *mut sys::AVPacket, time_base: sys::AVRational) {
+        let out_stream = *(*self.av).streams.add((*packet).stream_index as usize);
+
+        sys::av_packet_rescale_ts(packet, time_base, (*out_stream).time_base);
+        (*packet).pos = -1;
+
+        let response = sys::av_interleaved_write_frame(self.av, packet);
 
         utils::check_error(response);
     }
 }
+
+impl Drop for OutputCtx {
+    fn drop(&mut self) {
+        unsafe {
+            if (*(*self.av).oformat).flags & sys::AVFMT_NOFILE as i32 == 0 {
+                sys::avio_closep(&mut (*self.av).pb);
+            }
+
+            sys::avformat_free_context(self.av);
+        }
+    }
+}
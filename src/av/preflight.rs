@@ -0,0 +1,123 @@
+use ffmpeg_dev::sys;
+
+use super::input_ctx::InputCtx;
+use super::output_ctx::OutputCtx;
+use super::stream_map::StreamMap;
+use super::utils;
+
+pub enum Action {
+    Copy,
+    // the muxer can't hold the codec, but has a default one for this media type
+    Reencode(sys::AVCodecID),
+    Drop(&'static str),
+}
+
+// what happens to each mapped stream when stream-copied into a given output format
+pub struct Preflight {
+    // (input stream index, action)
+    pub actions: Vec<(usize, Action)>,
+}
+
+impl Preflight {
+    pub unsafe fn new(input: &InputCtx, output: &OutputCtx, map: &StreamMap) -> Preflight {
+        let oformat = (*output.av).oformat;
+        let mut actions = Vec::new();
+
+        for &i in map.inputs.iter() {
+            let codecpar = (*input.get_stream(i)).codecpar;
+
+            // 1 -> supported, 0 -> not supported, < 0 -> the muxer doesn't say
+            let supported = sys::avformat_query_codec(
+                oformat,
+                (*codecpar).codec_id,
+                sys::FF_COMPLIANCE_NORMAL as i32,
+            );
+
+            let action = if supported != 0 {
+                Action::Copy
+            } else {
+                let default_codec = match (*codecpar).codec_type {
+                    sys::AVMediaType_AVMEDIA_TYPE_VIDEO => (*oformat).video_codec,
+                    sys::AVMediaType_AVMEDIA_TYPE_AUDIO => (*oformat).audio_codec,
+                    _ => sys::AVCodecID_AV_CODEC_ID_NONE,
+                };
+
+                if default_codec == sys::AVCodecID_AV_CODEC_ID_NONE {
+                    Action::Drop("not supported by the container")
+                } else if sys::avcodec_find_encoder(default_codec).is_null() {
+                    Action::Drop("no encoder for the container's default codec")
+                } else {
+                    Action::Reencode(default_codec)
+                }
+            };
+
+            actions.push((i, action));
+        }
+
+        Preflight { actions }
+    }
+
+    pub fn compatible(&self) -> bool {
+        self.actions
+            .iter()
+            .all(|(_, action)| matches!(action, Action::Copy))
+    }
+
+    // codec the stream has to be re-encoded to, if any
+    pub fn reencode(&self, input_index: usize) -> Option<sys::AVCodecID> {
+        self.actions.iter().find_map(|(i, action)| match action {
+            Action::Reencode(codec_id) if *i == input_index => Some(*codec_id),
+            _ => None,
+        })
+    }
+
+    pub unsafe fn print(&self, input: &InputCtx, output: &OutputCtx) {
+        println!(
+            "Preflight for {} output:",
+            utils::c_str_to_string((*(*output.av).oformat).name)
+        );
+        println!("{:<8}{:<12}{:<16}{}", "Stream", "Type", "Codec", "Action");
+
+        for (i, action) in self.actions.iter() {
+            let codecpar = (*input.get_stream(*i)).codecpar;
+
+            let action = match action {
+                Action::Copy => "copy".to_string(),
+                Action::Reencode(codec_id) => format!(
+                    "re-encode to {}",
+                    utils::c_str_to_string(sys::avcodec_get_name(*codec_id))
+                ),
+                Action::Drop(reason) => format!("drop ({})", reason),
+            };
+
+            println!(
+                "{:<8}{:<12}{:<16}{}",
+                format!("#{}", i),
+                utils::c_str_to_string(sys::av_get_media_type_string((*codecpar).codec_type)),
+                utils::c_str_to_string(sys::avcodec_get_name((*codecpar).codec_id)),
+                action
+            );
+        }
+    }
+
+    // refuses to go on with incompatible streams unless `auto` is set,
+    // in which case the ones that can't be re-encoded are removed from the map
+    pub fn check(&self, map: &mut StreamMap, auto: bool) {
+        if self.compatible() {
+            return;
+        }
+
+        assert!(
+            auto,
+            "some streams can't be copied into this container, use --auto to re-encode or drop them"
+        );
+
+        for (i, action) in self.actions.iter() {
+            if let Action::Drop(_) = action {
+                map.remove(*i);
+            }
+        }
+
+        assert!(!map.inputs.is_empty(), "no streams left to write");
+    }
+}
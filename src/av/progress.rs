@@ -0,0 +1,202 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use ffmpeg_dev::sys;
+
+use super::input_ctx::InputCtx;
+use super::output_ctx::OutputCtx;
+use super::utils;
+
+extern "C" {
+    // from the C library std already links against
+    fn isatty(fd: i32) -> i32;
+}
+
+const STDERR_FILENO: i32 = 2;
+
+pub enum ProgressMode {
+    // a status line on stderr, only when it is a terminal
+    Auto,
+    // one json object per line on stderr, for job runners (stdout has the commands' own output)
+    Json,
+    None,
+}
+
+impl FromStr for ProgressMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ProgressMode::Auto),
+            "json" => Ok(ProgressMode::Json),
+            "none" => Ok(ProgressMode::None),
+            x => Err(format!("unknown progress mode '{}'", x)),
+        }
+    }
+}
+
+// tracks how far into the input the packets read so far are
+pub struct Progress {
+    enabled: bool,
+    json: bool,
+    // in AV_TIME_BASE units, 0 when unknown
+    // start and duration of the part of the input being processed, the whole of it by default
+    input_start: i64,
+    duration: i64,
+    position: i64,
+    video_stream: Option<usize>,
+    frames: u64,
+    started: Instant,
+    last_report: Option<Instant>,
+}
+
+impl Progress {
+    // `start` and `end` restrict the progress to a trimmed range, relative to the input start
+    // in AV_TIME_BASE units
+    pub unsafe fn new(
+        mode: &ProgressMode,
+        input: &InputCtx,
+        start: i64,
+        end: Option<i64>,
+    ) -> Progress {
+        let (enabled, json) = match mode {
+            ProgressMode::Auto => (isatty(STDERR_FILENO) == 1, false),
+            ProgressMode::Json => (true, true),
+            ProgressMode::None => (false, false),
+        };
+
+        let input_start = if (*input.av).start_time == utils::AV_NOPTS_VALUE {
+            0
+        } else {
+            (*input.av).start_time
+        };
+
+        let duration = if (*input.av).duration == utils::AV_NOPTS_VALUE {
+            0
+        } else {
+            (*input.av).duration
+        };
+
+        let duration = match end {
+            Some(end) if duration == 0 || end < duration => end - start,
+            _ => (duration - start).max(0),
+        };
+
+        Progress {
+            enabled,
+            json,
+            input_start: input_start + start,
+            duration,
+            position: 0,
+            video_stream: input
+                .find_stream(sys::AVMediaType_AVMEDIA_TYPE_VIDEO)
+                .map(|index| index as usize),
+            frames: 0,
+            started: Instant::now(),
+            last_report: None,
+        }
+    }
+
+    // to be called with every packet read from the input, reports at most twice per second
+    pub unsafe fn update(&mut self, input: &InputCtx, output: &OutputCtx) {
+        if !self.enabled {
+            return;
+        }
+
+        let packet = input.packet;
+        let index = (*packet).stream_index as usize;
+
+        if Some(index) == self.video_stream {
+            self.frames += 1;
+        }
+
+        if (*packet).pts != utils::AV_NOPTS_VALUE {
+            let time_base = (*input.get_stream(index)).time_base;
+            let position = sys::av_rescale_q((*packet).pts, time_base, utils::av_time_base_q())
+                - self.input_start;
+
+            self.position = self.position.max(position);
+        }
+
+        let due = self
+            .last_report
+            .map_or(true, |last| last.elapsed() >= Duration::from_millis(500));
+
+        if due {
+            self.report(output, false);
+            self.last_report = Some(Instant::now());
+        }
+    }
+
+    pub unsafe fn finish(&mut self, output: &OutputCtx) {
+        if !self.enabled {
+            return;
+        }
+
+        self.position = self.position.max(self.duration);
+        self.report(output, true);
+
+        if !self.json {
+            eprintln!();
+        }
+    }
+
+    unsafe fn report(&self, output: &OutputCtx, done: bool) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let position = self.position as f64 / sys::AV_TIME_BASE as f64;
+        let duration = self.duration as f64 / sys::AV_TIME_BASE as f64;
+
+        let percent = if self.duration > 0 {
+            (position / duration * 100.0).min(100.0)
+        } else {
+            0.0
+        };
+
+        let (speed, fps) = if elapsed > 0.0 {
+            (position / elapsed, self.frames as f64 / elapsed)
+        } else {
+            (0.0, 0.0)
+        };
+
+        let eta = if speed > 0.0 && self.duration > 0 {
+            ((duration - position) / speed).max(0.0)
+        } else {
+            0.0
+        };
+
+        // nothing is written to the file yet before the header, and some muxers have no file
+        let size = if (*output.av).pb.is_null() {
+            0
+        } else {
+            sys::avio_tell((*output.av).pb).max(0)
+        };
+
+        if self.json {
+            eprintln!(
+                "{{\"status\": \"{}\", \"percent\": {:.1}, \"time\": {:.3}, \"duration\": {:.3}, \"speed\": {:.2}, \"fps\": {:.1}, \"size\": {}, \"eta\": {:.1}}}",
+                if done { "done" } else { "running" },
+                percent,
+                position,
+                duration,
+                speed,
+                fps,
+                size,
+                eta
+            );
+        } else {
+            eprint!(
+                "\r{:5.1}% | {} / {} | {:.2}x | {:.0} fps | {:.1} MiB | ETA {}   ",
+                percent,
+                utils::format_timestamp(position),
+                utils::format_timestamp(duration),
+                speed,
+                fps,
+                size as f64 / (1024.0 * 1024.0),
+                utils::format_timestamp(eta)
+            );
+
+            io::stderr().flush().ok();
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::ptr::null_mut;
+
+use ffmpeg_dev::sys;
+
+use super::utils;
+
+pub struct Resampler {
+    pub swr: *mut sys::SwrContext,
+}
+
+impl Resampler {
+    pub unsafe fn new(
+        in_layout: i64,
+        in_format: sys::AVSampleFormat,
+        in_rate: i32,
+        out_layout: i64,
+        out_format: sys::AVSampleFormat,
+        out_rate: i32,
+    ) -> Resampler {
+        let swr = sys::swr_alloc_set_opts(
+            null_mut(),
+            out_layout,
+            out_format,
+            out_rate,
+            in_layout,
+            in_format,
+            in_rate,
+            0,
+            null_mut(),
+        );
+
+        assert!(swr != null_mut(), "failed to allocate resampling context");
+
+        let response = sys::swr_init(swr);
+        assert!(
+            !utils::check_error(response),
+            "failed to initialize resampler"
+        );
+
+        Resampler { swr }
+    }
+
+    // resampler matching a decoder's output
+    pub unsafe fn from_decoder(
+        codec_ctx: *const sys::AVCodecContext,
+        out_layout: i64,
+        out_format: sys::AVSampleFormat,
+        out_rate: i32,
+    ) -> Resampler {
+        Resampler::new(
+            utils::channel_layout(codec_ctx),
+            (*codec_ctx).sample_fmt,
+            (*codec_ctx).sample_rate,
+            out_layout,
+            out_format,
+            out_rate,
+        )
+    }
+
+    // upper bound of samples the next `convert` call can output
+    pub unsafe fn out_samples(&self, in_samples: i32) -> i32 {
+        sys::swr_get_out_samples(self.swr, in_samples)
+    }
+
+    // converts a frame into `out` (one pointer per output plane)
+    // a null frame flushes the samples buffered inside the resampler
+    // returns the number of samples per channel written
+    pub unsafe fn convert(
+        &mut self,
+        frame: *const sys::AVFrame,
+        out: *mut *mut u8,
+        out_samples: i32,
+    ) -> i32 {
+        let (input, in_samples) = if frame.is_null() {
+            (null_mut(), 0)
+        } else {
+            (
+                (*frame).extended_data as *mut *const u8,
+                (*frame).nb_samples,
+            )
+        };
+
+        let response = sys::swr_convert(self.swr, out, out_samples, input, in_samples);
+
+        utils::check_error(response);
+
+        response.max(0)
+    }
+}
+
+impl Drop for Resampler {
+    fn drop(&mut self) {
+        unsafe { sys::swr_free(&mut self.swr) }
+    }
+}
@@ -0,0 +1,63 @@
+use std::ptr::null_mut;
+
+use ffmpeg_dev::sys;
+
+use super::utils;
+
+pub struct Scaler {
+    pub sws: *mut sys::SwsContext,
+}
+
+impl Scaler {
+    pub fn new() -> Scaler {
+        Scaler { sws: null_mut() }
+    }
+
+    // scales/converts `src` into `dst`
+    // dst must already have its width, height, format and buffers set
+    pub unsafe fn scale(&mut self, src: *const sys::AVFrame, dst: *mut sys::AVFrame) {
+        // only re-created when the source or target geometry changes
+        self.sws = sys::sws_getCachedContext(
+            self.sws,
+            (*src).width,
+            (*src).height,
+            (*src).format,
+            (*dst).width,
+            (*dst).height,
+            (*dst).format,
+            sys::SWS_BICUBIC as i32,
+            null_mut(),
+            null_mut(),
+            null_mut(),
+        );
+
+        assert!(self.sws != null_mut(), "failed to create scaling context");
+
+        // an encoder may still hold a reference to the previous contents of dst
+        utils::check_error(sys::av_frame_make_writable(dst));
+
+        let response = sys::sws_scale(
+            self.sws,
+            (*src).data.as_ptr() as *const *const u8,
+            (*src).linesize.as_ptr(),
+            0,
+            (*src).height,
+            (*dst).data.as_ptr(),
+            (*dst).linesize.as_ptr(),
+        );
+
+        utils::check_error(response);
+    }
+}
+
+impl Default for Scaler {
+    fn default() -> Scaler {
+        Scaler::new()
+    }
+}
+
+impl Drop for Scaler {
+    fn drop(&mut self) {
+        unsafe { sys::sws_freeContext(self.sws) }
+    }
+}
@@ -0,0 +1,325 @@
+use std::ptr::null_mut;
+use std::str::FromStr;
+
+use ffmpeg_dev::sys;
+
+use super::input_ctx::InputCtx;
+use super::utils;
+
+#[derive(Clone)]
+pub enum StreamSelector {
+    Index(usize),
+    Type(sys::AVMediaType),
+    Codec(String),
+    Language(String),
+}
+
+// one `--map` entry
+// e.g. `1`, `v`, `a`, `codec=aac`, `lang=eng`, or any of those prefixed with `-` to exclude
+#[derive(Clone)]
+pub struct MapSpec {
+    pub exclude: bool,
+    pub selector: StreamSelector,
+}
+
+impl FromStr for MapSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (exclude, spec) = if s.starts_with("-") {
+            (true, &s[1..])
+        } else {
+            (false, s)
+        };
+
+        let selector = if spec.starts_with("codec=") {
+            StreamSelector::Codec(spec["codec=".len()..].to_string())
+        } else if spec.starts_with("lang=") {
+            StreamSelector::Language(spec["lang=".len()..].to_string())
+        } else if let Ok(index) = spec.parse() {
+            StreamSelector::Index(index)
+        } else {
+            StreamSelector::Type(parse_media_type(spec)?)
+        };
+
+        Ok(MapSpec { exclude, selector })
+    }
+}
+
+// `--map` entry for commands with several inputs, e.g. `0:v` or `1:lang=eng`
+pub struct InputMapSpec {
+    pub input: usize,
+    pub spec: MapSpec,
+}
+
+impl FromStr for InputMapSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+
+        let input = parts
+            .next()
+            .and_then(|input| input.parse().ok())
+            .ok_or_else(|| format!("invalid input index in '{}'", s))?;
+
+        let spec = parts
+            .next()
+            .ok_or_else(|| format!("missing stream specifier in '{}'", s))?
+            .parse()?;
+
+        Ok(InputMapSpec { input, spec })
+    }
+}
+
+// `--disposition` entry, e.g. `1=default+forced`, or `1=none` to clear them
+pub struct DispositionSpec {
+    pub stream: usize,
+    pub flags: i32,
+}
+
+impl FromStr for DispositionSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (stream, value) = stream_value(s)?;
+        let mut flags = 0;
+
+        for name in value.split('+').filter(|name| *name != "none") {
+            flags |= match name {
+                "default" => sys::AV_DISPOSITION_DEFAULT,
+                "dub" => sys::AV_DISPOSITION_DUB,
+                "original" => sys::AV_DISPOSITION_ORIGINAL,
+                "comment" | "commentary" => sys::AV_DISPOSITION_COMMENT,
+                "lyrics" => sys::AV_DISPOSITION_LYRICS,
+                "karaoke" => sys::AV_DISPOSITION_KARAOKE,
+                "forced" => sys::AV_DISPOSITION_FORCED,
+                "hearing_impaired" => sys::AV_DISPOSITION_HEARING_IMPAIRED,
+                "visual_impaired" => sys::AV_DISPOSITION_VISUAL_IMPAIRED,
+                "clean_effects" => sys::AV_DISPOSITION_CLEAN_EFFECTS,
+                "captions" => sys::AV_DISPOSITION_CAPTIONS,
+                "descriptions" => sys::AV_DISPOSITION_DESCRIPTIONS,
+                "metadata" => sys::AV_DISPOSITION_METADATA,
+                x => return Err(format!("unknown disposition '{}'", x)),
+            } as i32;
+        }
+
+        Ok(DispositionSpec { stream, flags })
+    }
+}
+
+// `--language` entry, e.g. `1=por`, with an ISO 639-2 code
+pub struct LanguageSpec {
+    pub stream: usize,
+    pub language: String,
+}
+
+impl FromStr for LanguageSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (stream, language) = stream_value(s)?;
+
+        if language.len() != 3 || !language.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(format!(
+                "invalid language '{}', expected a three letter ISO 639-2 code",
+                language
+            ));
+        }
+
+        Ok(LanguageSpec {
+            stream,
+            language: language.to_string(),
+        })
+    }
+}
+
+// splits `N=value`
+fn stream_value(s: &str) -> Result<(usize, &str), String> {
+    let mut parts = s.splitn(2, '=');
+
+    let stream = parts
+        .next()
+        .and_then(|stream| stream.parse().ok())
+        .ok_or_else(|| format!("invalid stream index in '{}'", s))?;
+
+    match parts.next() {
+        Some(value) if !value.is_empty() => Ok((stream, value)),
+        _ => Err(format!("missing value in '{}'", s)),
+    }
+}
+
+pub fn parse_media_type(s: &str) -> Result<sys::AVMediaType, String> {
+    match s {
+        "v" | "video" => Ok(sys::AVMediaType_AVMEDIA_TYPE_VIDEO),
+        "a" | "audio" => Ok(sys::AVMediaType_AVMEDIA_TYPE_AUDIO),
+        "s" | "subtitle" => Ok(sys::AVMediaType_AVMEDIA_TYPE_SUBTITLE),
+        "d" | "data" => Ok(sys::AVMediaType_AVMEDIA_TYPE_DATA),
+        "t" | "attachment" => Ok(sys::AVMediaType_AVMEDIA_TYPE_ATTACHMENT),
+        x => Err(format!("invalid stream specifier '{}'", x)),
+    }
+}
+
+// which input streams go to the output, and in which order
+pub struct StreamMap {
+    // input stream index of each output stream
+    pub inputs: Vec<usize>,
+    // output stream index of each input stream, if mapped
+    pub outputs: Vec<Option<usize>>,
+}
+
+impl StreamMap {
+    // without any inclusive spec all streams are selected, in input order
+    pub unsafe fn new(input: &InputCtx, specs: &[MapSpec], drop_data: bool) -> StreamMap {
+        let in_streams = input.get_streams();
+
+        let mut inputs: Vec<usize> = Vec::new();
+
+        if specs.iter().all(|spec| spec.exclude) {
+            inputs.extend(0..in_streams.len());
+        } else {
+            for spec in specs.iter().filter(|spec| !spec.exclude) {
+                for (i, stream) in in_streams.iter().enumerate() {
+                    if !inputs.contains(&i) && matches(*stream, &spec.selector) {
+                        inputs.push(i);
+                    }
+                }
+            }
+        }
+
+        inputs.retain(|&i| {
+            let stream = in_streams[i];
+
+            if specs
+                .iter()
+                .any(|spec| spec.exclude && matches(stream, &spec.selector))
+            {
+                return false;
+            }
+
+            let codec_type = (*(*stream).codecpar).codec_type;
+
+            !(drop_data
+                && (codec_type == sys::AVMediaType_AVMEDIA_TYPE_DATA
+                    || codec_type == sys::AVMediaType_AVMEDIA_TYPE_ATTACHMENT))
+        });
+
+        assert!(!inputs.is_empty(), "no input streams selected");
+
+        let mut map = StreamMap {
+            inputs,
+            outputs: vec![None; in_streams.len()],
+        };
+
+        map.update_outputs();
+
+        map
+    }
+
+    // takes an input stream out of the output, shifting the following output streams down
+    pub fn remove(&mut self, input_index: usize) {
+        self.inputs.retain(|&i| i != input_index);
+        self.update_outputs();
+    }
+
+    fn update_outputs(&mut self) {
+        for output in self.outputs.iter_mut() {
+            *output = None;
+        }
+
+        for (output_index, &input_index) in self.inputs.iter().enumerate() {
+            self.outputs[input_index] = Some(output_index);
+        }
+    }
+
+    pub fn output_index(&self, input_index: usize) -> Option<usize> {
+        self.outputs.get(input_index).cloned().flatten()
+    }
+
+    pub unsafe fn print(&self, input: &InputCtx) {
+        for (output_index, &input_index) in self.inputs.iter().enumerate() {
+            let codecpar = (*input.get_stream(input_index)).codecpar;
+
+            println!(
+                "Stream #{} -> #{} ({} {})",
+                input_index,
+                output_index,
+                utils::c_str_to_string(sys::av_get_media_type_string((*codecpar).codec_type)),
+                utils::c_str_to_string(sys::avcodec_get_name((*codecpar).codec_id))
+            );
+        }
+    }
+}
+
+unsafe fn matches(stream: *mut sys::AVStream, selector: &StreamSelector) -> bool {
+    let codecpar = (*stream).codecpar;
+
+    match selector {
+        StreamSelector::Index(index) => (*stream).index as usize == *index,
+        StreamSelector::Type(media_type) => (*codecpar).codec_type == *media_type,
+        StreamSelector::Codec(name) => {
+            utils::c_str_to_string(sys::avcodec_get_name((*codecpar).codec_id)) == *name
+        }
+        StreamSelector::Language(language) => {
+            stream_language(stream).map_or(false, |l| l == *language)
+        }
+    }
+}
+
+pub unsafe fn stream_language(stream: *mut sys::AVStream) -> Option<String> {
+    stream_tag(stream, "language")
+}
+
+// applies the `--disposition` / `--language` entries addressing output stream `index`
+pub unsafe fn tag_output_stream(
+    stream: *mut sys::AVStream,
+    index: usize,
+    dispositions: &[DispositionSpec],
+    languages: &[LanguageSpec],
+) {
+    if let Some(spec) = dispositions.iter().rev().find(|spec| spec.stream == index) {
+        (*stream).disposition = spec.flags;
+    }
+
+    if let Some(spec) = languages.iter().rev().find(|spec| spec.stream == index) {
+        set_stream_language(stream, &spec.language);
+    }
+}
+
+pub fn check_output_tags(
+    stream_count: usize,
+    dispositions: &[DispositionSpec],
+    languages: &[LanguageSpec],
+) {
+    let indices = dispositions
+        .iter()
+        .map(|spec| spec.stream)
+        .chain(languages.iter().map(|spec| spec.stream));
+
+    for index in indices {
+        assert!(
+            index < stream_count,
+            "output stream #{} does not exist, there are {} output streams",
+            index,
+            stream_count
+        );
+    }
+}
+
+pub unsafe fn set_stream_language(stream: *mut sys::AVStream, language: &str) {
+    let key = utils::str_to_c_str("language");
+    let value = utils::str_to_c_str(language);
+
+    sys::av_dict_set(&mut (*stream).metadata, key.as_ptr(), value.as_ptr(), 0);
+}
+
+pub unsafe fn stream_tag(stream: *mut sys::AVStream, key: &str) -> Option<String> {
+    let key = utils::str_to_c_str(key);
+    let entry = sys::av_dict_get((*stream).metadata, key.as_ptr(), null_mut(), 0);
+
+    if entry == null_mut() {
+        None
+    } else {
+        Some(utils::c_str_to_string((*entry).value))
+    }
+}
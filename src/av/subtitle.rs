@@ -0,0 +1,136 @@
+use std::mem;
+
+use ffmpeg_dev::sys;
+
+use super::decoder::Decoder;
+use super::encoder::Encoder;
+use super::output_ctx::OutputCtx;
+use super::utils;
+
+// converts text subtitles between codecs, e.g. srt to mov_text for mp4
+// subtitles don't go through the send/receive api, so this uses the legacy decode/encode calls
+pub struct SubtitleConverter {
+    decoder: Decoder,
+    encoder: Encoder,
+    buffer: Vec<u8>,
+}
+
+impl SubtitleConverter {
+    // replaces the parameters of `out_stream` with the encoder's
+    pub unsafe fn new(
+        in_stream: *mut sys::AVStream,
+        codec_id: sys::AVCodecID,
+        output: &OutputCtx,
+        out_stream: *mut sys::AVStream,
+    ) -> SubtitleConverter {
+        let decoder = Decoder::new(in_stream);
+        let mut encoder = Encoder::for_codec(codec_id);
+
+        // text encoders need the ass header the decoder generated (styles, play resolution)
+        let header = (*decoder.codec_ctx).subtitle_header;
+        let header_size = (*decoder.codec_ctx).subtitle_header_size;
+
+        if !header.is_null() {
+            let copy = sys::av_mallocz(header_size as usize + 1) as *mut u8;
+            copy.copy_from_nonoverlapping(header, header_size as usize);
+
+            (*encoder.codec_ctx).subtitle_header = copy;
+            (*encoder.codec_ctx).subtitle_header_size = header_size;
+        }
+
+        (*encoder.codec_ctx).time_base = utils::av_time_base_q();
+
+        encoder.open(output);
+
+        sys::avcodec_parameters_from_context((*out_stream).codecpar, encoder.codec_ctx);
+        (*out_stream).time_base = utils::av_time_base_q();
+
+        SubtitleConverter {
+            decoder,
+            encoder,
+            buffer: vec![0; 1 << 16],
+        }
+    }
+
+    // converts one packet of the input stream and writes it to `stream_index`
+    pub unsafe fn write(
+        &mut self,
+        packet: *const sys::AVPacket,
+        output: &mut OutputCtx,
+        stream_index: usize,
+    ) {
+        let mut subtitle: sys::AVSubtitle = mem::zeroed();
+        let mut got_subtitle = 0;
+
+        let response = sys::avcodec_decode_subtitle2(
+            self.decoder.codec_ctx,
+            &mut subtitle,
+            &mut got_subtitle,
+            packet as *mut sys::AVPacket,
+        );
+
+        if utils::check_error(response) || got_subtitle == 0 {
+            return;
+        }
+
+        if subtitle.pts == utils::AV_NOPTS_VALUE {
+            sys::avsubtitle_free(&mut subtitle);
+            return;
+        }
+
+        // the decoder gives pts in AV_TIME_BASE units and display times relative to it
+        // in milliseconds, fold the start into pts
+        let pts = subtitle.pts + subtitle.start_display_time as i64 * 1000;
+        let duration = (subtitle.end_display_time - subtitle.start_display_time) as i64 * 1000;
+
+        subtitle.pts = pts;
+        subtitle.end_display_time -= subtitle.start_display_time;
+        subtitle.start_display_time = 0;
+
+        let size = sys::avcodec_encode_subtitle(
+            self.encoder.codec_ctx,
+            self.buffer.as_mut_ptr(),
+            self.buffer.len() as i32,
+            &subtitle,
+        );
+
+        sys::avsubtitle_free(&mut subtitle);
+
+        if utils::check_error(size) || size == 0 {
+            return;
+        }
+
+        let mut out_packet = sys::av_packet_alloc();
+        sys::av_new_packet(out_packet, size);
+        (*out_packet)
+            .data
+            .copy_from_nonoverlapping(self.buffer.as_ptr(), size as usize);
+
+        (*out_packet).pts = pts;
+        (*out_packet).dts = pts;
+        (*out_packet).duration = duration;
+        (*out_packet).stream_index = stream_index as i32;
+
+        output.write_packet(out_packet, utils::av_time_base_q());
+
+        sys::av_packet_free(&mut out_packet);
+    }
+}
+
+// codec a subtitle stream gets in the output: itself when the container takes it as is,
+// otherwise the container's default subtitle codec (e.g. mov_text for mp4)
+pub unsafe fn output_codec(codec_id: sys::AVCodecID, output: &OutputCtx) -> sys::AVCodecID {
+    let oformat = (*output.av).oformat;
+
+    if sys::avformat_query_codec(oformat, codec_id, sys::FF_COMPLIANCE_NORMAL as i32) == 1 {
+        return codec_id;
+    }
+
+    assert!(
+        (*oformat).subtitle_codec != sys::AVCodecID_AV_CODEC_ID_NONE,
+        "{} output does not support subtitles",
+        utils::c_str_to_string((*oformat).name)
+    );
+
+    (*oformat).subtitle_codec
+}
@@ -0,0 +1,122 @@
+use std::ptr::null_mut;
+
+use ffmpeg_dev::sys;
+
+use super::decoder::Decoder;
+use super::encoder::Encoder;
+use super::input_ctx::InputCtx;
+use super::mp4::Mp4Mode;
+use super::output_ctx::OutputCtx;
+use super::utils;
+
+pub const WIDTH: i32 = 320;
+pub const HEIGHT: i32 = 240;
+pub const FRAME_RATE: i32 = 25;
+
+// a file-name in the temp directory, unique to the test process
+pub fn temp_path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!(
+            "rust-ffmpeg-examples-{}-{}",
+            std::process::id(),
+            name
+        ))
+        .to_string_lossy()
+        .to_string()
+}
+
+// writes `frames` frames of a moving gradient as h264, with B-frames and a keyframe every 10
+pub unsafe fn write_video(path: &str, frames: i64, mp4_mode: Option<&Mp4Mode>) {
+    let mut output = OutputCtx::open(path, None);
+    let mut encoder = Encoder::for_codec(sys::AVCodecID_AV_CODEC_ID_H264);
+    let encoder_ctx = encoder.codec_ctx;
+
+    (*encoder_ctx).width = WIDTH;
+    (*encoder_ctx).height = HEIGHT;
+    (*encoder_ctx).pix_fmt = sys::AVPixelFormat_AV_PIX_FMT_YUV420P;
+    (*encoder_ctx).time_base = sys::AVRational {
+        num: 1,
+        den: FRAME_RATE,
+    };
+    (*encoder_ctx).framerate = sys::AVRational {
+        num: FRAME_RATE,
+        den: 1,
+    };
+    (*encoder_ctx).gop_size = 10;
+    (*encoder_ctx).max_b_frames = 2;
+
+    encoder.open(&output);
+    encoder.new_stream(&mut output);
+    output.set_mp4_mode(mp4_mode, None);
+    output.open_file_with_options(path, &[]);
+
+    let mut frame = utils::alloc_frame(WIDTH, HEIGHT, (*encoder_ctx).pix_fmt);
+
+    for i in 0..frames {
+        sys::av_frame_make_writable(frame);
+
+        for plane in 0..3 {
+            let (width, height) = if plane == 0 {
+                (WIDTH, HEIGHT)
+            } else {
+                (WIDTH / 2, HEIGHT / 2)
+            };
+            let linesize = (*frame).linesize[plane] as usize;
+            let data = (*frame).data[plane];
+
+            for y in 0..height as usize {
+                for x in 0..width as usize {
+                    let value = if plane == 0 {
+                        x + y + i as usize * 4
+                    } else {
+                        128
+                    };
+                    *data.add(y * linesize + x) = value as u8;
+                }
+            }
+        }
+
+        (*frame).pts = i;
+        encoder.send_frame(frame);
+        encoder.write_packets(&mut output, 0);
+    }
+
+    encoder.send_frame(null_mut());
+    encoder.write_packets(&mut output, 0);
+
+    sys::av_write_trailer(output.av);
+    sys::av_frame_free(&mut frame);
+}
+
+// decodes the video stream of a file, returning how many frames came out of it, and panics on
+// packets the decoder rejects
+pub unsafe fn decoded_frames(path: &str) -> i32 {
+    let input = InputCtx::new(path);
+    let index = input.find_video_stream();
+    let mut decoder = Decoder::new(input.get_stream(index as usize));
+    let mut frame = sys::av_frame_alloc();
+    let mut count = 0;
+
+    while sys::av_read_frame(input.av, input.packet) >= 0 {
+        if (*input.packet).stream_index == index {
+            let response = decoder.send_packet(input.packet);
+            assert!(response >= 0, "failed to decode a packet of {}", path);
+
+            while decoder.receive_frame(frame) {
+                count += 1;
+            }
+        }
+
+        sys::av_packet_unref(input.packet);
+    }
+
+    decoder.send_packet(null_mut());
+
+    while decoder.receive_frame(frame) {
+        count += 1;
+    }
+
+    sys::av_frame_free(&mut frame);
+
+    count
+}
@@ -0,0 +1,204 @@
+use std::ptr::null_mut;
+
+use ffmpeg_dev::sys;
+
+use super::input_ctx::InputCtx;
+use super::utils;
+
+struct StreamState {
+    // expected packet duration in the stream time base, 0 when unknown
+    frame_duration: i64,
+    last_dts: Option<i64>,
+    last_duration: i64,
+    corrected: usize,
+}
+
+impl StreamState {
+    fn new(frame_duration: i64) -> StreamState {
+        StreamState {
+            frame_duration,
+            last_dts: None,
+            last_duration: 0,
+            corrected: 0,
+        }
+    }
+}
+
+// regenerates missing or non-monotonic packet timestamps from the frame durations
+pub struct TimestampFixer {
+    streams: Vec<StreamState>,
+}
+
+impl TimestampFixer {
+    pub unsafe fn new(input: &InputCtx) -> TimestampFixer {
+        let streams = input
+            .get_streams()
+            .iter()
+            .map(|&stream| StreamState::new(frame_duration(input, stream)))
+            .collect();
+
+        TimestampFixer { streams }
+    }
+
+    // returns whether the packet's timestamps had to be changed
+    pub unsafe fn fix(&mut self, packet: *mut sys::AVPacket) -> bool {
+        let state = &mut self.streams[(*packet).stream_index as usize];
+        let mut corrected = false;
+
+        if (*packet).duration <= 0 && state.frame_duration > 0 {
+            (*packet).duration = state.frame_duration;
+        }
+
+        // a negative dts is valid (b-frame delay, edit lists) and left as is, the muxer moves
+        // all streams by the same amount when the container needs it (avoid_negative_ts),
+        // keeping them in sync
+        let dts = (*packet).dts;
+        let broken = dts == utils::AV_NOPTS_VALUE
+            || state.last_dts.map_or(false, |last_dts| dts <= last_dts);
+
+        if broken {
+            // continue right after the previous packet
+            let step = state.last_duration.max(1);
+            let fixed_dts = state.last_dts.map_or(0, |last_dts| last_dts + step);
+
+            // pts moves along with dts, keeping the reordering delay of b-frames
+            if dts != utils::AV_NOPTS_VALUE && (*packet).pts != utils::AV_NOPTS_VALUE {
+                (*packet).pts += fixed_dts - dts;
+            }
+
+            (*packet).dts = fixed_dts;
+            corrected = true;
+        }
+
+        // pts can only be missing here, or behind a dts that had to be made up
+        if (*packet).pts == utils::AV_NOPTS_VALUE || (*packet).pts < (*packet).dts {
+            (*packet).pts = (*packet).dts;
+            corrected = true;
+        }
+
+        state.last_dts = Some((*packet).dts);
+        state.last_duration = (*packet).duration;
+
+        if corrected {
+            state.corrected += 1;
+        }
+
+        corrected
+    }
+
+    pub unsafe fn report(&self, input: &InputCtx) {
+        for (i, state) in self.streams.iter().enumerate() {
+            if state.corrected == 0 {
+                continue;
+            }
+
+            let time_base = (*input.get_stream(i)).time_base;
+            let frame_duration = state.frame_duration as f64 * utils::av_q2d(time_base);
+
+            println!(
+                "Stream #{}: corrected timestamps of {} packets (frame duration {:.4}s)",
+                i, state.corrected, frame_duration
+            );
+        }
+
+        let total: usize = self.streams.iter().map(|state| state.corrected).sum();
+        println!("Corrected timestamps of {} packets in total", total);
+    }
+}
+
+// duration of one frame in the stream time base, from the frame rate for video and the frame
+// size for audio
+unsafe fn frame_duration(input: &InputCtx, stream: *mut sys::AVStream) -> i64 {
+    let codecpar = (*stream).codecpar;
+    let time_base = (*stream).time_base;
+
+    match (*codecpar).codec_type {
+        sys::AVMediaType_AVMEDIA_TYPE_VIDEO => {
+            let rate = sys::av_guess_frame_rate(input.av, stream, null_mut());
+
+            if rate.num > 0 && rate.den > 0 {
+                sys::av_rescale_q(1, utils::av_inv_q(rate), time_base)
+            } else {
+                0
+            }
+        }
+        sys::AVMediaType_AVMEDIA_TYPE_AUDIO
+            if (*codecpar).frame_size > 0 && (*codecpar).sample_rate > 0 =>
+        {
+            let sample_rate = sys::AVRational {
+                num: 1,
+                den: (*codecpar).sample_rate,
+            };
+
+            sys::av_rescale_q((*codecpar).frame_size as i64, sample_rate, time_base)
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a single stream with one tick per frame
+    fn fixer() -> TimestampFixer {
+        TimestampFixer {
+            streams: vec![StreamState::new(1)],
+        }
+    }
+
+    // fixes packets with the given (dts, pts), returning the resulting ones
+    unsafe fn fix_all(fixer: &mut TimestampFixer, timestamps: &[(i64, i64)]) -> Vec<(i64, i64)> {
+        let mut packet = sys::av_packet_alloc();
+        let mut fixed = Vec::new();
+
+        for &(dts, pts) in timestamps {
+            (*packet).dts = dts;
+            (*packet).pts = pts;
+            (*packet).duration = 0;
+
+            fixer.fix(packet);
+            fixed.push(((*packet).dts, (*packet).pts));
+        }
+
+        sys::av_packet_free(&mut packet);
+
+        fixed
+    }
+
+    #[test]
+    fn keeps_valid_negative_dts() {
+        let mut fixer = fixer();
+        // b-frame delay: dts starts before 0, pts at 0
+        let timestamps = [(-2, 0), (-1, 3), (0, 1), (1, 2), (2, 6)];
+
+        let fixed = unsafe { fix_all(&mut fixer, &timestamps) };
+
+        assert_eq!(fixed, timestamps.to_vec());
+        assert_eq!(fixer.streams[0].corrected, 0);
+    }
+
+    #[test]
+    fn regenerates_broken_dts() {
+        let mut fixer = fixer();
+        let none = utils::AV_NOPTS_VALUE;
+        let timestamps = [(0, 1), (1, 2), (1, 3), (none, 4), (10, 11)];
+
+        let fixed = unsafe { fix_all(&mut fixer, &timestamps) };
+
+        // the repeated dts moves to the next tick with its pts, the missing one follows it
+        assert_eq!(fixed, vec![(0, 1), (1, 2), (2, 4), (3, 4), (10, 11)]);
+        assert_eq!(fixer.streams[0].corrected, 2);
+    }
+
+    #[test]
+    fn fills_missing_pts() {
+        let mut fixer = fixer();
+        let none = utils::AV_NOPTS_VALUE;
+
+        let fixed = unsafe { fix_all(&mut fixer, &[(0, none), (1, 0)]) };
+
+        assert_eq!(fixed, vec![(0, 0), (1, 1)]);
+        assert_eq!(fixer.streams[0].corrected, 2);
+    }
+}
@@ -0,0 +1,309 @@
+use std::ptr::null_mut;
+
+use ffmpeg_dev::sys;
+
+use super::audio_fifo::AudioFifo;
+use super::decoder::Decoder;
+use super::encoder::Encoder;
+use super::input_ctx::InputCtx;
+use super::output_ctx::OutputCtx;
+use super::resampler::Resampler;
+use super::scaler::Scaler;
+use super::utils;
+
+// decodes a single input stream and re-encodes it with another codec, keeping the geometry
+// of the source, audio is resampled / remixed to whatever the encoder was set up with
+pub struct StreamTranscoder {
+    pub decoder: Decoder,
+    pub encoder: Encoder,
+    scaler: Scaler,
+    resampler: Option<Resampler>,
+    fifo: Option<AudioFifo>,
+    // decoded frame, and its converted copy for video
+    frame: *mut sys::AVFrame,
+    enc_frame: *mut sys::AVFrame,
+    // input stream time base, and the shift added to its timestamps
+    time_base: sys::AVRational,
+    shift: i64,
+    // pts of the next audio frame, in the encoder time base
+    next_pts: Option<i64>,
+    // whether audio timing starts over from the next decoded frame, after a new input
+    restart: bool,
+}
+
+impl StreamTranscoder {
+    // replaces the parameters of `out_stream` with the encoder's
+    pub unsafe fn new(
+        input: &InputCtx,
+        in_stream: *mut sys::AVStream,
+        codec_id: sys::AVCodecID,
+        output: &OutputCtx,
+        out_stream: *mut sys::AVStream,
+    ) -> StreamTranscoder {
+        let encoder = Encoder::for_codec(codec_id);
+
+        StreamTranscoder::with_encoder(input, in_stream, encoder, output, out_stream)
+    }
+
+    // for an encoder (not opened yet) that may come with a sample rate and channel count of
+    // its own, e.g. from --ar / --ac, anything unset is taken from the source
+    pub unsafe fn with_encoder(
+        input: &InputCtx,
+        in_stream: *mut sys::AVStream,
+        mut encoder: Encoder,
+        output: &OutputCtx,
+        out_stream: *mut sys::AVStream,
+    ) -> StreamTranscoder {
+        let codecpar = (*in_stream).codecpar;
+        let decoder = Decoder::new(in_stream);
+
+        let mut enc_frame = null_mut();
+
+        if (*codecpar).codec_type == sys::AVMediaType_AVMEDIA_TYPE_VIDEO {
+            let framerate = sys::av_guess_frame_rate(input.av, in_stream, null_mut());
+
+            // keep the source pixel format when the encoder supports it
+            let pix_fmt = if (*encoder.codec).pix_fmts.is_null() {
+                (*codecpar).format
+            } else {
+                sys::avcodec_find_best_pix_fmt_of_list(
+                    (*encoder.codec).pix_fmts,
+                    (*codecpar).format,
+                    0,
+                    null_mut(),
+                )
+            };
+
+            (*encoder.codec_ctx).width = (*codecpar).width;
+            (*encoder.codec_ctx).height = (*codecpar).height;
+            (*encoder.codec_ctx).pix_fmt = pix_fmt;
+            (*encoder.codec_ctx).sample_aspect_ratio = (*codecpar).sample_aspect_ratio;
+            (*encoder.codec_ctx).framerate = framerate;
+            (*encoder.codec_ctx).time_base = utils::av_inv_q(framerate);
+
+            enc_frame = utils::alloc_frame((*codecpar).width, (*codecpar).height, pix_fmt);
+        } else {
+            let sample_rate = match (*encoder.codec_ctx).sample_rate {
+                0 => (*codecpar).sample_rate,
+                sample_rate => sample_rate,
+            };
+
+            let supported_rate = supported_sample_rate(&encoder, sample_rate);
+
+            if supported_rate != sample_rate {
+                println!(
+                    "warning: encoder does not support {} Hz, using {} Hz",
+                    sample_rate, supported_rate
+                );
+            }
+
+            // remixing to a different channel count uses that count's default layout
+            let channel_layout = match (*encoder.codec_ctx).channels {
+                0 => utils::channel_layout(decoder.codec_ctx),
+                channels => sys::av_get_default_channel_layout(channels),
+            };
+
+            (*encoder.codec_ctx).sample_rate = supported_rate;
+            (*encoder.codec_ctx).channel_layout = channel_layout as u64;
+            (*encoder.codec_ctx).channels =
+                sys::av_get_channel_layout_nb_channels(channel_layout as u64);
+            (*encoder.codec_ctx).sample_fmt = encoder.default_sample_fmt();
+            (*encoder.codec_ctx).time_base = sys::AVRational {
+                num: 1,
+                den: (*encoder.codec_ctx).sample_rate,
+            };
+        }
+
+        encoder.open(output);
+
+        sys::avcodec_parameters_from_context((*out_stream).codecpar, encoder.codec_ctx);
+        (*out_stream).time_base = (*encoder.codec_ctx).time_base;
+
+        let (resampler, fifo) = if enc_frame.is_null() {
+            (
+                Some(resampler(&decoder, &encoder)),
+                Some(AudioFifo::new(encoder.codec_ctx)),
+            )
+        } else {
+            (None, None)
+        };
+
+        StreamTranscoder {
+            decoder,
+            encoder,
+            scaler: Scaler::new(),
+            resampler,
+            fifo,
+            frame: sys::av_frame_alloc(),
+            enc_frame,
+            time_base: (*in_stream).time_base,
+            shift: 0,
+            next_pts: None,
+            restart: true,
+        }
+    }
+
+    // switches to another stream of the same kind, e.g. the next input of a concatenation,
+    // whose timestamps get `shift` (in its own time base) added
+    // whatever the previous decoder still buffers should be `drain`ed first
+    pub unsafe fn set_input(&mut self, in_stream: *mut sys::AVStream, shift: i64) {
+        self.decoder = Decoder::new(in_stream);
+
+        if self.resampler.is_some() {
+            self.resampler = Some(resampler(&self.decoder, &self.encoder));
+        }
+
+        self.time_base = (*in_stream).time_base;
+        self.shift = shift;
+        self.restart = true;
+    }
+
+    // decodes a packet of the input stream and writes whatever gets encoded to `stream_index`
+    pub unsafe fn send_packet(
+        &mut self,
+        packet: *const sys::AVPacket,
+        output: &mut OutputCtx,
+        stream_index: usize,
+    ) {
+        self.decoder.send_packet(packet);
+
+        while self.decoder.receive_frame(self.frame) {
+            self.encode_frame(output, stream_index);
+        }
+    }
+
+    // encodes what the decoder and the resampler still buffer, keeping the encoder (and any
+    // samples short of a whole frame) for more input
+    pub unsafe fn drain(&mut self, output: &mut OutputCtx, stream_index: usize) {
+        self.send_packet(null_mut(), output, stream_index);
+
+        if let (Some(fifo), Some(resampler)) = (self.fifo.as_mut(), self.resampler.as_mut()) {
+            fifo.write(resampler, null_mut());
+        }
+
+        self.encode_audio(false, output, stream_index);
+    }
+
+    // drains the decoder, the resampler and the encoder
+    pub unsafe fn flush(&mut self, output: &mut OutputCtx, stream_index: usize) {
+        self.drain(output, stream_index);
+        self.encode_audio(true, output, stream_index);
+
+        self.encoder.send_frame(null_mut());
+        self.encoder.write_packets(output, stream_index as i32);
+    }
+
+    unsafe fn encode_frame(&mut self, output: &mut OutputCtx, stream_index: usize) {
+        let ts = (*self.frame).best_effort_timestamp;
+
+        // in the encoder time base
+        let pts = if ts == utils::AV_NOPTS_VALUE {
+            ts
+        } else {
+            sys::av_rescale_q(
+                ts + self.shift,
+                self.time_base,
+                (*self.encoder.codec_ctx).time_base,
+            )
+        };
+
+        if !self.enc_frame.is_null() {
+            self.scaler.scale(self.frame, self.enc_frame);
+
+            (*self.enc_frame).pts = pts;
+
+            self.encoder.send_frame(self.enc_frame);
+            self.encoder.write_packets(output, stream_index as i32);
+        } else if let (Some(fifo), Some(resampler)) = (self.fifo.as_mut(), self.resampler.as_mut())
+        {
+            // audio is re-timed by counting samples from the first decoded frame of each input,
+            // never going back in time
+            if self.restart {
+                let start = if pts == utils::AV_NOPTS_VALUE {
+                    self.next_pts.unwrap_or(0)
+                } else {
+                    pts
+                };
+
+                self.next_pts = Some(self.next_pts.map_or(start, |next| next.max(start)));
+                self.restart = false;
+            }
+
+            fifo.write(resampler, self.frame);
+            self.encode_audio(false, output, stream_index);
+        }
+    }
+
+    // feeds the encoder whole frames from the fifo, and the remainder too when flushing
+    unsafe fn encode_audio(&mut self, flush: bool, output: &mut OutputCtx, stream_index: usize) {
+        let fifo = match self.fifo.as_mut() {
+            Some(fifo) => fifo,
+            None => return,
+        };
+
+        // encoders with variable frame size report 0
+        let frame_size = match (*self.encoder.codec_ctx).frame_size {
+            0 => 1024,
+            size => size,
+        };
+
+        let mut next_pts = self.next_pts.unwrap_or(0);
+
+        while fifo.len() >= frame_size || (flush && !fifo.is_empty()) {
+            let mut frame = fifo.read(frame_size);
+
+            (*frame).pts = next_pts;
+            next_pts += (*frame).nb_samples as i64;
+
+            self.encoder.send_frame(frame);
+            self.encoder.write_packets(output, stream_index as i32);
+
+            sys::av_frame_free(&mut frame);
+        }
+
+        self.next_pts = Some(next_pts);
+    }
+}
+
+impl Drop for StreamTranscoder {
+    fn drop(&mut self) {
+        unsafe {
+            sys::av_frame_free(&mut self.frame);
+
+            if !self.enc_frame.is_null() {
+                sys::av_frame_free(&mut self.enc_frame);
+            }
+        }
+    }
+}
+
+// converts the decoder's samples to the encoder's format, layout and rate
+unsafe fn resampler(decoder: &Decoder, encoder: &Encoder) -> Resampler {
+    Resampler::from_decoder(
+        decoder.codec_ctx,
+        utils::channel_layout(encoder.codec_ctx),
+        (*encoder.codec_ctx).sample_fmt,
+        (*encoder.codec_ctx).sample_rate,
+    )
+}
+
+// the source rate if the encoder accepts it, otherwise the first one it declares
+unsafe fn supported_sample_rate(encoder: &Encoder, sample_rate: i32) -> i32 {
+    let mut rate = (*encoder.codec).supported_samplerates;
+
+    if rate.is_null() {
+        return sample_rate;
+    }
+
+    let first = *rate;
+
+    while *rate != 0 {
+        if *rate == sample_rate {
+            return sample_rate;
+        }
+
+        rate = rate.add(1);
+    }
+
+    first
+}
@@ -2,6 +2,12 @@ use ffmpeg_dev::sys;
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
 
+// bindgen can't translate these macros
+pub const AV_NOPTS_VALUE: i64 = i64::MIN;
+// FFERRTAG(0xF8, 'O', 'P', 'T')
+pub const AVERROR_OPTION_NOT_FOUND: i32 =
+    -(0xF8 | ('O' as i32) << 8 | ('P' as i32) << 16 | ('T' as i32) << 24);
+
 pub unsafe fn averror_to_str(error: i32) -> String {
     let c_str = sys::strerror(error);
 
@@ -30,3 +36,161 @@ pub fn av_inv_q(n: sys::AVRational) -> sys::AVRational {
         den: n.num,
     }
 }
+
+pub fn av_q2d(n: sys::AVRational) -> f64 {
+    n.num as f64 / n.den as f64
+}
+
+pub fn av_time_base_q() -> sys::AVRational {
+    sys::AVRational {
+        num: 1,
+        den: sys::AV_TIME_BASE as i32,
+    }
+}
+
+pub unsafe fn alloc_frame(width: i32, height: i32, format: i32) -> *mut sys::AVFrame {
+    let frame = sys::av_frame_alloc();
+
+    (*frame).width = width;
+    (*frame).height = height;
+    (*frame).format = format;
+
+    let response = sys::av_frame_get_buffer(frame, 32);
+    assert!(!check_error(response), "failed to allocate frame buffers");
+
+    frame
+}
+
+pub fn format_timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as i64;
+
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+// writes a report to `path`, or to stdout when no path is given
+pub fn write_report(path: &Option<String>, contents: &str) {
+    match path {
+        Some(path) => std::fs::write(path, contents).expect("could not write report"),
+        None => print!("{}", contents),
+    }
+}
+
+// codec extradata of a stream, empty when it has none
+pub unsafe fn extradata<'a>(codecpar: *const sys::AVCodecParameters) -> &'a [u8] {
+    if (*codecpar).extradata.is_null() || (*codecpar).extradata_size <= 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts((*codecpar).extradata, (*codecpar).extradata_size as usize)
+    }
+}
+
+// copies `rows` lines of `row_size` bytes from a frame plane, dropping the linesize padding
+pub unsafe fn copy_plane(
+    frame: *const sys::AVFrame,
+    plane: usize,
+    row_size: i32,
+    rows: i32,
+) -> Vec<u8> {
+    let linesize = (*frame).linesize[plane] as isize;
+    let mut data = Vec::with_capacity((row_size * rows) as usize);
+
+    for y in 0..rows as isize {
+        let line = std::slice::from_raw_parts(
+            (*frame).data[plane].offset(y * linesize),
+            row_size as usize,
+        );
+        data.extend_from_slice(line);
+    }
+
+    data
+}
+
+// mean absolute difference between two luma samples, normalized to [0, 1]
+pub fn luma_difference(a: &[u8], b: &[u8]) -> f64 {
+    let sad: u64 = a
+        .iter()
+        .zip(b.iter())
+        .map(|(x, y)| (*x as i32 - *y as i32).abs() as u64)
+        .sum();
+
+    sad as f64 / (a.len() as f64 * 255.0)
+}
+
+// builds an AVDictionary from key/value pairs, to be freed with av_dict_free
+pub unsafe fn dictionary(options: &[(String, String)]) -> *mut sys::AVDictionary {
+    let mut dict = std::ptr::null_mut();
+
+    for (key, value) in options {
+        let key_str = str_to_c_str(key);
+        let value_str = str_to_c_str(value);
+
+        sys::av_dict_set(&mut dict, key_str.as_ptr(), value_str.as_ptr(), 0);
+    }
+
+    dict
+}
+
+// parses frame rates such as "25", "29.97", "30000/1001" or "ntsc"
+pub unsafe fn parse_video_rate(rate: &str) -> sys::AVRational {
+    let rate_str = str_to_c_str(rate);
+    let mut rational = sys::AVRational { num: 0, den: 1 };
+
+    let response = sys::av_parse_video_rate(&mut rational, rate_str.as_ptr());
+    assert!(!check_error(response), "invalid frame rate {}", rate);
+
+    rational
+}
+
+pub unsafe fn parse_pix_fmt(name: &str) -> sys::AVPixelFormat {
+    let name_str = str_to_c_str(name);
+    let pix_fmt = sys::av_get_pix_fmt(name_str.as_ptr());
+
+    assert!(
+        pix_fmt != sys::AVPixelFormat_AV_PIX_FMT_NONE,
+        "unknown pixel format {}",
+        name
+    );
+
+    pix_fmt
+}
+
+// some decoders leave the layout unset and only report a channel count
+pub unsafe fn channel_layout(codec_ctx: *const sys::AVCodecContext) -> i64 {
+    if (*codec_ctx).channel_layout != 0 {
+        (*codec_ctx).channel_layout as i64
+    } else {
+        sys::av_get_default_channel_layout((*codec_ctx).channels)
+    }
+}
+
+// parses "90", "1.5", "01:30" or "00:01:30.500" into AV_TIME_BASE units
+pub unsafe fn parse_time(time: &str) -> i64 {
+    let time_str = str_to_c_str(time);
+    let mut value = 0;
+
+    let response = sys::av_parse_time(&mut value, time_str.as_ptr(), 1);
+    assert!(!check_error(response), "invalid time {}", time);
+
+    value
+}
+
+// parses sizes in bytes, with an optional k/M/G suffix (powers of 1024), e.g. "500M"
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let (digits, multiplier) = match size.chars().last() {
+        Some('k') | Some('K') => (&size[..size.len() - 1], 1 << 10),
+        Some('m') | Some('M') => (&size[..size.len() - 1], 1 << 20),
+        Some('g') | Some('G') => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+
+    digits
+        .parse::<u64>()
+        .map(|value| value * multiplier)
+        .map_err(|_| format!("invalid size '{}'", size))
+}
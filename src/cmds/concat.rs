@@ -0,0 +1,261 @@
+use std::path::PathBuf;
+use std::ptr::null_mut;
+
+use ffmpeg_dev::sys;
+
+use crate::av::encoder::Encoder;
+use crate::av::input_ctx::InputCtx;
+use crate::av::output_ctx::OutputCtx;
+use crate::av::transcoder::StreamTranscoder;
+use crate::av::utils;
+use crate::opts;
+
+pub fn run(args: opts::Concat) {
+    assert!(args.inputs.len() >= 2, "concat needs at least two inputs");
+
+    for path in args.inputs.iter() {
+        assert!(PathBuf::from(path).exists(), "file {} does not exist", path);
+    }
+
+    unsafe {
+        let input_format = args.input_format.format.as_deref();
+        let demuxer_opts = args.input_format.options();
+
+        let inputs: Vec<InputCtx> = args
+            .inputs
+            .iter()
+            .map(|p| InputCtx::open(p, input_format, &demuxer_opts))
+            .collect();
+
+        let issues = check_compatibility(&args.inputs, &inputs);
+
+        for issue in issues.iter() {
+            println!("{}", issue);
+        }
+
+        let reencode = !issues.is_empty();
+
+        assert!(
+            !reencode || args.reencode,
+            "inputs have different codec parameters, use --reencode to convert them"
+        );
+
+        let mut output =
+            OutputCtx::open(args.output.as_str(), args.output_format.format.as_deref());
+        // streams without a transcoder are stream-copied
+        let mut transcoders: Vec<Option<StreamTranscoder>> = Vec::new();
+
+        for &in_stream in inputs[0].get_streams().iter() {
+            let codec_type = (*(*in_stream).codecpar).codec_type;
+
+            let out_stream = sys::avformat_new_stream(output.av, null_mut());
+            assert!(out_stream != null_mut(), "failed to allocate output stream");
+
+            let transcoder = if reencode
+                && (codec_type == sys::AVMediaType_AVMEDIA_TYPE_VIDEO
+                    || codec_type == sys::AVMediaType_AVMEDIA_TYPE_AUDIO)
+            {
+                Some(new_transcoder(in_stream, &inputs[0], &output, out_stream))
+            } else {
+                copy_params(in_stream, out_stream);
+                None
+            };
+
+            transcoders.push(transcoder);
+        }
+
+        output.open_file_with_options(args.output.as_str(), &args.output_format.options());
+
+        // where the current input starts in the output, in AV_TIME_BASE units
+        let mut offset = 0;
+
+        for (path, input) in args.inputs.iter().zip(inputs.iter()) {
+            println!(
+                "Appending {} at {}",
+                path,
+                utils::format_timestamp(offset as f64 / sys::AV_TIME_BASE as f64)
+            );
+
+            let duration = append(input, offset, &mut transcoders, &mut output);
+            offset += duration;
+        }
+
+        // flush encoders
+        for (index, transcoder) in transcoders.iter_mut().enumerate() {
+            if let Some(transcoder) = transcoder {
+                transcoder.flush(&mut output, index);
+            }
+        }
+
+        sys::av_write_trailer(output.av);
+    }
+}
+
+// lists every difference that prevents stream-copying the inputs back to back
+unsafe fn check_compatibility(paths: &[String], inputs: &[InputCtx]) -> Vec<String> {
+    let reference = inputs[0].get_streams();
+    let mut issues = Vec::new();
+
+    for (path, input) in paths.iter().zip(inputs.iter()).skip(1) {
+        let streams = input.get_streams();
+
+        assert_eq!(
+            streams.len(),
+            reference.len(),
+            "{} has {} streams, expected {}",
+            path,
+            streams.len(),
+            reference.len()
+        );
+
+        for (i, (a, b)) in reference.iter().zip(streams.iter()).enumerate() {
+            let a = &*(**a).codecpar;
+            let b = &*(**b).codecpar;
+
+            assert_eq!(
+                a.codec_type, b.codec_type,
+                "stream #{} of {} has a different media type",
+                i, path
+            );
+
+            let mut differences = Vec::new();
+
+            if a.codec_id != b.codec_id {
+                differences.push("codec");
+            }
+
+            if a.format != b.format {
+                differences.push("pixel/sample format");
+            }
+
+            if a.width != b.width || a.height != b.height {
+                differences.push("resolution");
+            }
+
+            if a.sample_rate != b.sample_rate || a.channels != b.channels {
+                differences.push("sample rate/channels");
+            }
+
+            if utils::extradata(a) != utils::extradata(b) {
+                differences.push("codec extradata");
+            }
+
+            if !differences.is_empty() {
+                issues.push(format!(
+                    "{} stream #{}: different {}",
+                    path,
+                    i,
+                    differences.join(", ")
+                ));
+            }
+        }
+    }
+
+    issues
+}
+
+unsafe fn copy_params(in_stream: *mut sys::AVStream, out_stream: *mut sys::AVStream) {
+    let response = sys::avcodec_parameters_copy((*out_stream).codecpar, (*in_stream).codecpar);
+    utils::check_error(response);
+
+    (*(*out_stream).codecpar).codec_tag = 0;
+}
+
+// re-encodes to the codec, geometry / sample rate / channels and bitrate of the first input
+unsafe fn new_transcoder(
+    in_stream: *mut sys::AVStream,
+    input: &InputCtx,
+    output: &OutputCtx,
+    out_stream: *mut sys::AVStream,
+) -> StreamTranscoder {
+    let codecpar = (*in_stream).codecpar;
+    let encoder = Encoder::for_codec((*codecpar).codec_id);
+
+    (*encoder.codec_ctx).bit_rate = (*codecpar).bit_rate;
+
+    StreamTranscoder::with_encoder(input, in_stream, encoder, output, out_stream)
+}
+
+// writes a whole input shifted by `offset`, returns its duration in AV_TIME_BASE units
+unsafe fn append(
+    input: &InputCtx,
+    offset: i64,
+    transcoders: &mut [Option<StreamTranscoder>],
+    output: &mut OutputCtx,
+) -> i64 {
+    let in_streams = input.get_streams();
+
+    let input_start = if (*input.av).start_time == utils::AV_NOPTS_VALUE {
+        0
+    } else {
+        (*input.av).start_time
+    };
+
+    // shift of each stream, in its own time base
+    let shifts: Vec<i64> = in_streams
+        .iter()
+        .map(|s| {
+            sys::av_rescale_q(
+                offset - input_start,
+                utils::av_time_base_q(),
+                (**s).time_base,
+            )
+        })
+        .collect();
+
+    for (index, transcoder) in transcoders.iter_mut().enumerate() {
+        if let Some(transcoder) = transcoder {
+            transcoder.set_input(in_streams[index], shifts[index]);
+        }
+    }
+
+    let mut end = 0;
+
+    while sys::av_read_frame(input.av, input.packet) >= 0 {
+        let index = (*input.packet).stream_index as usize;
+
+        if index >= transcoders.len() {
+            sys::av_packet_unref(input.packet);
+            continue;
+        }
+
+        let in_stream = in_streams[index];
+        let packet = input.packet;
+
+        if (*packet).pts != utils::AV_NOPTS_VALUE {
+            let packet_end = sys::av_rescale_q(
+                (*packet).pts + (*packet).duration,
+                (*in_stream).time_base,
+                utils::av_time_base_q(),
+            ) - input_start;
+
+            end = end.max(packet_end);
+        }
+
+        match transcoders[index].as_mut() {
+            Some(transcoder) => transcoder.send_packet(packet, output, index),
+            None => {
+                if (*packet).pts != utils::AV_NOPTS_VALUE {
+                    (*packet).pts += shifts[index];
+                }
+
+                if (*packet).dts != utils::AV_NOPTS_VALUE {
+                    (*packet).dts += shifts[index];
+                }
+
+                output.write_packet(packet, (*in_stream).time_base);
+            }
+        }
+
+        sys::av_packet_unref(input.packet);
+    }
+
+    // drain decoders, their buffered frames still belong to this input
+    for (index, transcoder) in transcoders.iter_mut().enumerate() {
+        if let Some(transcoder) = transcoder {
+            transcoder.drain(output, index);
+        }
+    }
+
+    end
+}
@@ -0,0 +1,118 @@
+use std::path::PathBuf;
+
+use ffmpeg_dev::sys;
+
+use crate::av::image::Image;
+use crate::av::input_ctx::InputCtx;
+use crate::av::scaler::Scaler;
+use crate::av::utils;
+use crate::opts;
+
+const BACKGROUND: [u8; 3] = [16, 16, 16];
+const TEXT_COLOR: [u8; 3] = [255, 255, 255];
+const TEXT_SHADOW: [u8; 3] = [0, 0, 0];
+const TEXT_SCALE: i32 = 2;
+
+pub fn run(args: opts::ContactSheet) {
+    let path = args.input.as_str();
+
+    assert!(PathBuf::from(path).exists(), "file {} does not exist", path);
+    assert!(
+        args.rows > 0 && args.columns > 0,
+        "rows and columns must be positive"
+    );
+
+    unsafe {
+        let mut ctx = InputCtx::new(path);
+
+        let index = ctx.find_video_stream();
+        ctx.open_video_stream(index);
+
+        let stream = ctx.get_stream(index as usize);
+        let time_base = (*stream).time_base;
+        let (start, duration) = ctx.stream_range(index as usize);
+
+        // keep the source aspect ratio, with an even height
+        let source_width = (*ctx.codec).width;
+        let source_height = (*ctx.codec).height;
+        let thumb_width = args.width;
+        let thumb_height = (thumb_width * source_height / source_width + 1) & !1;
+
+        let padding = args.padding;
+        let mut sheet = Image::new(
+            args.columns * thumb_width + (args.columns + 1) * padding,
+            args.rows * thumb_height + (args.rows + 1) * padding,
+            BACKGROUND,
+        );
+
+        let mut scaler = Scaler::new();
+        let mut thumb_frame = utils::alloc_frame(
+            thumb_width,
+            thumb_height,
+            sys::AVPixelFormat_AV_PIX_FMT_RGB24,
+        );
+
+        let tiles = args.rows * args.columns;
+
+        for i in 0..tiles {
+            // pick the middle of each of the N equal slices of the video
+            let target = start + duration * (2 * i as i64 + 1) / (2 * tiles as i64);
+
+            ctx.seek(target);
+
+            if !read_frame_at(&mut ctx, target) {
+                println!("Ran out of frames after {} of {} tiles", i, tiles);
+                break;
+            }
+
+            let pts = (*ctx.frame).best_effort_timestamp;
+            let seconds = (pts - start) as f64 * utils::av_q2d(time_base);
+
+            println!("Tile {} at {}", i, utils::format_timestamp(seconds));
+
+            scaler.scale(ctx.frame, thumb_frame);
+            let mut thumb = Image::from_frame(thumb_frame);
+
+            if !args.no_timestamps {
+                draw_timestamp(&mut thumb, seconds);
+            }
+
+            let x = padding + (i % args.columns) * (thumb_width + padding);
+            let y = padding + (i / args.columns) * (thumb_height + padding);
+            sheet.blit(&thumb, x, y);
+        }
+
+        sys::av_frame_free(&mut thumb_frame);
+
+        println!("Saving contact sheet into {}", args.output);
+        sheet.save(args.output.as_str());
+    }
+}
+
+// decodes forward from the last seek point until reaching `target`
+unsafe fn read_frame_at(ctx: &mut InputCtx, target: i64) -> bool {
+    while ctx.read_video_frame() {
+        if (*ctx.frame).best_effort_timestamp >= target {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn draw_timestamp(thumb: &mut Image, seconds: f64) {
+    let text = utils::format_timestamp(seconds);
+    let (width, height) = Image::text_size(&text, TEXT_SCALE);
+
+    let x = thumb.width - width - 2 * TEXT_SCALE;
+    let y = thumb.height - height - 2 * TEXT_SCALE;
+
+    thumb.draw_text(
+        &text,
+        x + TEXT_SCALE,
+        y + TEXT_SCALE,
+        TEXT_SCALE,
+        TEXT_SHADOW,
+    );
+    thumb.draw_text(&text, x, y, TEXT_SCALE, TEXT_COLOR);
+}
@@ -0,0 +1,651 @@
+use std::path::PathBuf;
+use std::ptr::null_mut;
+
+use ffmpeg_dev::sys;
+
+use crate::av::bsf::{self, Bsf};
+use crate::av::decoder::Decoder;
+use crate::av::encoder::Encoder;
+use crate::av::input_ctx::InputCtx;
+use crate::av::output_ctx::OutputCtx;
+use crate::av::utils;
+use crate::opts;
+
+// which part of the cut a video packet falls in
+#[derive(PartialEq, Clone, Copy)]
+enum Segment {
+    // partial GOP before the first keyframe in range, re-encoded
+    Head,
+    // whole GOPs, stream-copied
+    Copy,
+    // partial GOP after the last keyframe in range, re-encoded
+    Tail,
+}
+
+// all timestamps in the video stream time base
+struct Plan {
+    start: i64,
+    end: Option<i64>,
+    // [copy_start, copy_end) is stream-copied, everything else in range is re-encoded
+    copy_start: i64,
+    copy_end: Option<i64>,
+    // pts - dts of the first copied keyframe, i.e. the reordering delay of the source, which
+    // re-encoded packets (no reordering) get on their dts so they splice with the copied ones
+    dts_delay: i64,
+}
+
+impl Plan {
+    fn segment(&self, pts: i64) -> Segment {
+        if pts < self.copy_start {
+            Segment::Head
+        } else if self.copy_end.map_or(false, |end| pts >= end) {
+            Segment::Tail
+        } else {
+            Segment::Copy
+        }
+    }
+
+    fn in_range(&self, pts: i64) -> bool {
+        pts >= self.start && self.end.map_or(true, |end| pts < end)
+    }
+}
+
+struct VideoState {
+    index: usize,
+    time_base: sys::AVRational,
+    codecpar: *const sys::AVCodecParameters,
+    decoder: Decoder,
+    encoder: Option<Encoder>,
+    // puts the source headers in-band on copied keyframes, for length-prefixed sources
+    filter: Option<Bsf>,
+    // nal length size of the output extradata, when re-encoded (annex b) packets need converting
+    length_size: Option<usize>,
+    segment: Segment,
+    packet: *mut sys::AVPacket,
+    encoded_frames: i32,
+    copied_packets: i32,
+}
+
+pub fn run(args: opts::Cut) {
+    let input_path = args.input.as_str();
+    let output_path = args.output.as_str();
+
+    assert!(
+        PathBuf::from(input_path).exists(),
+        "file {} does not exist",
+        input_path
+    );
+
+    unsafe {
+        let mut input = InputCtx::open(
+            input_path,
+            args.input_format.format.as_deref(),
+            &args.input_format.options(),
+        );
+        let mut output = OutputCtx::open(output_path, args.output_format.format.as_deref());
+
+        let video_index = input.find_video_stream() as usize;
+        let video_stream = input.get_stream(video_index);
+        let time_base = (*video_stream).time_base;
+
+        let plan = plan(&args, &mut input, video_index);
+
+        println!(
+            "Re-encoding {} -> {}, copying {} -> {}, re-encoding {} -> {}",
+            seconds(plan.start, time_base),
+            seconds(plan.copy_start, time_base),
+            seconds(plan.copy_start, time_base),
+            plan.copy_end
+                .map_or("end".to_string(), |t| seconds(t, time_base)),
+            plan.copy_end
+                .map_or("end".to_string(), |t| seconds(t, time_base)),
+            plan.end
+                .map_or("end".to_string(), |t| seconds(t, time_base)),
+        );
+
+        let in_streams = input.get_streams();
+        let mut out_streams = Vec::new();
+
+        for in_stream in in_streams.iter() {
+            let out_stream = sys::avformat_new_stream(output.av, null_mut());
+            assert!(out_stream != null_mut(), "failed to allocate output stream");
+
+            let response =
+                sys::avcodec_parameters_copy((*out_stream).codecpar, (**in_stream).codecpar);
+            utils::check_error(response);
+
+            // let the muxer pick the tag for its own container
+            (*(*out_stream).codecpar).codec_tag = 0;
+
+            out_streams.push(out_stream);
+        }
+
+        let codecpar = (*video_stream).codecpar;
+        let global_header = (*(*output.av).oformat).flags & sys::AVFMT_GLOBALHEADER as i32 != 0;
+
+        // re-encoded GOPs carry their own headers in-band, so copied keyframes need the source
+        // ones in-band as well for the decoder to switch back to them
+        let filter = annexb_filter(codecpar).map(|name| Bsf::new(name, video_stream));
+
+        // with global headers (e.g. mp4) the container keeps the source extradata, and all
+        // packets go back to its nal unit framing
+        let length_size = if global_header {
+            nal_length_size(codecpar)
+        } else {
+            None
+        };
+
+        // otherwise (e.g. mpegts) the stream is annex b throughout
+        match &filter {
+            Some(filter) if !global_header => {
+                let out_codecpar = (*out_streams[video_index]).codecpar;
+                sys::avcodec_parameters_copy(out_codecpar, filter.output_params());
+                (*out_codecpar).codec_tag = 0;
+            }
+            _ => {}
+        }
+
+        output.open_file_with_options(output_path, &args.output_format.options());
+
+        let mut video = VideoState {
+            index: video_index,
+            time_base,
+            codecpar,
+            decoder: Decoder::new(video_stream),
+            encoder: None,
+            filter,
+            length_size,
+            segment: Segment::Head,
+            packet: sys::av_packet_alloc(),
+            encoded_frames: 0,
+            copied_packets: 0,
+        };
+
+        let response = sys::av_seek_frame(
+            input.av,
+            video_index as i32,
+            plan.start,
+            sys::AVSEEK_FLAG_BACKWARD as i32,
+        );
+        utils::check_error(response);
+
+        while sys::av_read_frame(input.av, input.packet) >= 0 {
+            let index = (*input.packet).stream_index as usize;
+
+            if index == video_index {
+                if !handle_video(&mut video, &plan, &mut input, &mut output) {
+                    sys::av_packet_unref(input.packet);
+                    break;
+                }
+            } else {
+                copy_other(&plan, &input, &mut output, time_base);
+            }
+
+            sys::av_packet_unref(input.packet);
+        }
+
+        // decode and encode whatever is still buffered
+        if video.segment != Segment::Copy {
+            decode_and_encode(&mut video, &plan, null_mut(), &mut output);
+        }
+
+        finish_encoder(&mut video, &plan, &mut output);
+
+        sys::av_write_trailer(output.av);
+        sys::av_packet_free(&mut video.packet);
+
+        println!(
+            "Re-encoded {} frames, copied {} packets",
+            video.encoded_frames, video.copied_packets
+        );
+    }
+}
+
+// finds the keyframes delimiting the stream-copied middle of the range
+unsafe fn plan(args: &opts::Cut, input: &mut InputCtx, video_index: usize) -> Plan {
+    let time_base = (*input.get_stream(video_index)).time_base;
+    let (stream_start, _) = input.stream_range(video_index);
+
+    let to_stream_tb =
+        |time: i64| stream_start + sys::av_rescale_q(time, utils::av_time_base_q(), time_base);
+
+    let start_time = utils::parse_time(&args.start);
+    let start = to_stream_tb(start_time);
+    let end = match (&args.end, &args.duration) {
+        (Some(_), Some(_)) => panic!("--end and --duration are mutually exclusive"),
+        (Some(end), None) => Some(to_stream_tb(utils::parse_time(end))),
+        (None, Some(duration)) => Some(to_stream_tb(start_time + utils::parse_time(duration))),
+        (None, None) => None,
+    };
+
+    if let Some(end) = end {
+        assert!(end > start, "end must be after start");
+    }
+
+    // scan packets (without decoding) for keyframes within the range
+    let response = sys::av_seek_frame(
+        input.av,
+        video_index as i32,
+        start,
+        sys::AVSEEK_FLAG_BACKWARD as i32,
+    );
+    utils::check_error(response);
+
+    let mut keyframes = Vec::new();
+    let mut dts_delay = 0;
+
+    while sys::av_read_frame(input.av, input.packet) >= 0 {
+        let packet = *input.packet;
+        sys::av_packet_unref(input.packet);
+
+        if packet.stream_index as usize != video_index
+            || packet.flags & sys::AV_PKT_FLAG_KEY as i32 == 0
+            || packet.pts == utils::AV_NOPTS_VALUE
+        {
+            continue;
+        }
+
+        if end.map_or(false, |end| packet.pts >= end) {
+            break;
+        }
+
+        if packet.pts >= start {
+            if keyframes.is_empty() && packet.dts != utils::AV_NOPTS_VALUE {
+                dts_delay = (packet.pts - packet.dts).max(0);
+            }
+
+            keyframes.push(packet.pts);
+
+            // with no end, only the head needs re-encoding, up to this keyframe
+            if end.is_none() {
+                break;
+            }
+        }
+    }
+
+    let (copy_start, copy_end) = match (keyframes.first(), keyframes.last()) {
+        // with no end, everything after the first keyframe can be copied
+        (Some(&first), _) if end.is_none() => (first, None),
+        // at least one whole GOP between the first and last keyframes
+        (Some(&first), Some(&last)) if first != last => (first, Some(last)),
+        // not a single whole GOP in range, re-encode all of it
+        _ => (end.unwrap_or(i64::max_value()), end),
+    };
+
+    Plan {
+        start,
+        end,
+        copy_start,
+        copy_end,
+        dts_delay,
+    }
+}
+
+// returns false once the video stream is past the end of the range
+unsafe fn handle_video(
+    video: &mut VideoState,
+    plan: &Plan,
+    input: &mut InputCtx,
+    output: &mut OutputCtx,
+) -> bool {
+    let packet = input.packet;
+    let pts = if (*packet).pts != utils::AV_NOPTS_VALUE {
+        (*packet).pts
+    } else {
+        (*packet).dts
+    };
+
+    let is_key = (*packet).flags & sys::AV_PKT_FLAG_KEY as i32 != 0;
+
+    // keep feeding the decoder past the end until the next keyframe,
+    // as reordered frames still inside the range may depend on those packets
+    if plan.end.map_or(false, |end| pts >= end) && is_key {
+        return false;
+    }
+
+    let segment = if is_key {
+        plan.segment(pts)
+    } else {
+        video.segment
+    };
+
+    if segment != video.segment {
+        if video.segment == Segment::Head {
+            // drain the head GOP before switching to copying
+            decode_and_encode(video, plan, null_mut(), output);
+            finish_encoder(video, plan, output);
+        }
+
+        // the decoder starts over from a keyframe
+        sys::avcodec_flush_buffers(video.decoder.codec_ctx);
+        video.segment = segment;
+    }
+
+    match segment {
+        Segment::Copy => {
+            shift(packet, plan.start, plan.start);
+
+            match video.filter.as_mut() {
+                Some(filter) => {
+                    filter.send_packet(packet);
+
+                    while filter.receive_packet(video.packet) {
+                        if let Some(length_size) = video.length_size {
+                            to_length_prefixed(video.packet, length_size);
+                        }
+
+                        output.write_packet(video.packet, filter.time_base());
+                        sys::av_packet_unref(video.packet);
+                    }
+                }
+                None => output.write_packet(packet, video.time_base),
+            }
+
+            video.copied_packets += 1;
+        }
+        Segment::Head | Segment::Tail => {
+            decode_and_encode(video, plan, packet, output);
+        }
+    }
+
+    true
+}
+
+// stream-copies other streams (audio, subtitles...) within the range
+unsafe fn copy_other(
+    plan: &Plan,
+    input: &InputCtx,
+    output: &mut OutputCtx,
+    video_time_base: sys::AVRational,
+) {
+    let packet = input.packet;
+    let index = (*packet).stream_index as usize;
+    let time_base = (*input.get_stream(index)).time_base;
+
+    if (*packet).pts == utils::AV_NOPTS_VALUE {
+        return;
+    }
+
+    // compare in the video time base, which the plan uses
+    let pts = sys::av_rescale_q((*packet).pts, time_base, video_time_base);
+
+    if !plan.in_range(pts) {
+        return;
+    }
+
+    let offset = sys::av_rescale_q(plan.start, video_time_base, time_base);
+    shift(packet, offset, offset);
+    output.write_packet(packet, time_base);
+}
+
+// a null packet drains the decoder
+unsafe fn decode_and_encode(
+    video: &mut VideoState,
+    plan: &Plan,
+    packet: *mut sys::AVPacket,
+    output: &mut OutputCtx,
+) {
+    let mut frame = sys::av_frame_alloc();
+
+    video.decoder.send_packet(packet);
+
+    while video.decoder.receive_frame(frame) {
+        let pts = (*frame).best_effort_timestamp;
+
+        // frames decoded only as references for the ones in range
+        if !plan.in_range(pts) || plan.segment(pts) != video.segment {
+            sys::av_frame_unref(frame);
+            continue;
+        }
+
+        if video.encoder.is_none() {
+            video.encoder = Some(new_encoder(video));
+        }
+
+        (*frame).pts = pts - plan.start;
+        (*frame).pict_type = sys::AVPictureType_AV_PICTURE_TYPE_NONE;
+
+        if let Some(encoder) = video.encoder.as_mut() {
+            encoder.send_frame(frame);
+        }
+
+        video.encoded_frames += 1;
+        write_encoded(video, plan, output);
+
+        sys::av_frame_unref(frame);
+    }
+
+    sys::av_frame_free(&mut frame);
+}
+
+// an encoder configured from the source codec parameters, so its output can be spliced with
+// the copied packets
+unsafe fn new_encoder(video: &VideoState) -> Encoder {
+    let codecpar = video.codecpar;
+    let decoder_ctx = video.decoder.codec_ctx;
+    let mut encoder = Encoder::for_codec((*codecpar).codec_id);
+    let encoder_ctx = encoder.codec_ctx;
+
+    (*encoder_ctx).width = (*codecpar).width;
+    (*encoder_ctx).height = (*codecpar).height;
+    (*encoder_ctx).pix_fmt = (*codecpar).format;
+    (*encoder_ctx).sample_aspect_ratio = (*codecpar).sample_aspect_ratio;
+    (*encoder_ctx).time_base = video.time_base;
+    (*encoder_ctx).framerate = (*decoder_ctx).framerate;
+    (*encoder_ctx).profile = (*codecpar).profile;
+    (*encoder_ctx).level = (*codecpar).level;
+    (*encoder_ctx).color_range = (*codecpar).color_range;
+    (*encoder_ctx).color_primaries = (*codecpar).color_primaries;
+    (*encoder_ctx).color_trc = (*codecpar).color_trc;
+    (*encoder_ctx).colorspace = (*codecpar).color_space;
+    (*encoder_ctx).chroma_sample_location = (*codecpar).chroma_location;
+    (*encoder_ctx).field_order = (*codecpar).field_order;
+
+    // a bit above the source bitrate, as short segments are harder to compress
+    if (*codecpar).bit_rate > 0 {
+        (*encoder_ctx).bit_rate = (*codecpar).bit_rate * 5 / 4;
+    }
+
+    // no reordering, so the re-encoded dts never overlap with the copied ones
+    (*encoder_ctx).max_b_frames = 0;
+
+    // the output keeps the source headers (in extradata or on the copied keyframes), so the
+    // re-encoded GOPs bring their own in-band
+    encoder.open_in_band();
+
+    encoder
+}
+
+// the filter turning a length-prefixed (mp4/mkv) stream to annex b with in-band headers
+unsafe fn annexb_filter(codecpar: *const sys::AVCodecParameters) -> Option<&'static str> {
+    if !bsf::length_prefixed(codecpar) {
+        return None;
+    }
+
+    match (*codecpar).codec_id {
+        sys::AVCodecID_AV_CODEC_ID_H264 => Some("h264_mp4toannexb"),
+        sys::AVCodecID_AV_CODEC_ID_HEVC => Some("hevc_mp4toannexb"),
+        _ => None,
+    }
+}
+
+// bytes of the nal unit length prefixes declared by avcC/hvcC extradata, none for annex b
+unsafe fn nal_length_size(codecpar: *const sys::AVCodecParameters) -> Option<usize> {
+    if !bsf::length_prefixed(codecpar) {
+        return None;
+    }
+
+    let data = utils::extradata(codecpar);
+
+    let position = match (*codecpar).codec_id {
+        sys::AVCodecID_AV_CODEC_ID_H264 => 4,
+        sys::AVCodecID_AV_CODEC_ID_HEVC => 21,
+        _ => return None,
+    };
+
+    data.get(position).map(|byte| (byte & 3) as usize + 1)
+}
+
+// nal units between 00 00 01 start codes
+fn nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut starts = Vec::new();
+    let mut units = Vec::new();
+
+    for i in 0..data.len().saturating_sub(2) {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            starts.push(i + 3);
+        }
+    }
+
+    for (n, &start) in starts.iter().enumerate() {
+        let mut end = starts.get(n + 1).map_or(data.len(), |&next| next - 3);
+
+        // trailing zeros belong to the next (4 byte) start code
+        while end > start && data[end - 1] == 0 {
+            end -= 1;
+        }
+
+        if end > start {
+            units.push(&data[start..end]);
+        }
+    }
+
+    units
+}
+
+// the nal units of annex b data, each prefixed by its size in `length_size` bytes
+fn length_prefixed_units(data: &[u8], length_size: usize) -> Vec<u8> {
+    let mut converted = Vec::with_capacity(data.len() + 16);
+
+    for unit in nal_units(data) {
+        converted.extend_from_slice(&(unit.len() as u32).to_be_bytes()[4 - length_size..]);
+        converted.extend_from_slice(unit);
+    }
+
+    converted
+}
+
+// replaces the data of an annex b packet with its length-prefixed units
+unsafe fn to_length_prefixed(packet: *mut sys::AVPacket, length_size: usize) {
+    let data = std::slice::from_raw_parts((*packet).data, (*packet).size as usize);
+    let converted = length_prefixed_units(data, length_size);
+
+    let mut converted_packet = sys::av_packet_alloc();
+    let response = sys::av_new_packet(converted_packet, converted.len() as i32);
+    assert!(!utils::check_error(response), "failed to allocate packet");
+
+    std::ptr::copy_nonoverlapping(
+        converted.as_ptr(),
+        (*converted_packet).data,
+        converted.len(),
+    );
+    sys::av_packet_copy_props(converted_packet, packet);
+
+    sys::av_packet_unref(packet);
+    sys::av_packet_move_ref(packet, converted_packet);
+    sys::av_packet_free(&mut converted_packet);
+}
+
+unsafe fn write_encoded(video: &mut VideoState, plan: &Plan, output: &mut OutputCtx) {
+    if let Some(encoder) = video.encoder.as_mut() {
+        while encoder.receive_packet(video.packet) {
+            (*video.packet).stream_index = video.index as i32;
+            // frames were sent with pts relative to the start already
+            shift(video.packet, 0, plan.dts_delay);
+
+            if let Some(length_size) = video.length_size {
+                to_length_prefixed(video.packet, length_size);
+            }
+
+            output.write_packet(video.packet, video.time_base);
+            sys::av_packet_unref(video.packet);
+        }
+    }
+}
+
+// flushes and drops the current encoder, a new one is created for the next re-encoded segment
+unsafe fn finish_encoder(video: &mut VideoState, plan: &Plan, output: &mut OutputCtx) {
+    if let Some(encoder) = video.encoder.as_mut() {
+        encoder.send_frame(null_mut());
+    }
+
+    write_encoded(video, plan, output);
+    video.encoder = None;
+}
+
+// moves a packet by constant offsets, the same for a whole segment so the presentation order
+// within it is kept
+unsafe fn shift(packet: *mut sys::AVPacket, pts_offset: i64, dts_offset: i64) {
+    if (*packet).pts != utils::AV_NOPTS_VALUE {
+        (*packet).pts -= pts_offset;
+    }
+
+    if (*packet).dts != utils::AV_NOPTS_VALUE {
+        (*packet).dts -= dts_offset;
+    }
+}
+
+fn seconds(ts: i64, time_base: sys::AVRational) -> String {
+    utils::format_timestamp(ts as f64 * utils::av_q2d(time_base))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::av::testing;
+    use clap::Clap;
+
+    #[test]
+    fn annexb_to_length_prefixed() {
+        let annexb = [
+            0, 0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x68, 3, 0, 0, 0, 1, 0x65, 4, 5, 6,
+        ];
+
+        assert_eq!(
+            length_prefixed_units(&annexb, 4),
+            vec![0, 0, 0, 3, 0x67, 1, 2, 0, 0, 0, 2, 0x68, 3, 0, 0, 0, 4, 0x65, 4, 5, 6]
+        );
+        assert_eq!(
+            length_prefixed_units(&annexb, 2),
+            vec![0, 3, 0x67, 1, 2, 0, 2, 0x68, 3, 0, 4, 0x65, 4, 5, 6]
+        );
+    }
+
+    // cuts 0.6s -> 2.6s of an mp4 source with a keyframe every 0.4s, so that there is a
+    // re-encoded head and tail around the copied GOPs
+    fn cut_mp4_to(extension: &str) {
+        let source = testing::temp_path(&format!("cut-source-{}.mp4", extension));
+        let output = testing::temp_path(&format!("cut.{}", extension));
+
+        unsafe { testing::write_video(&source, 75, None) };
+
+        run(opts::Cut::parse_from(vec![
+            "cut",
+            "-i",
+            source.as_str(),
+            "-o",
+            output.as_str(),
+            "-s",
+            "0.6",
+            "-e",
+            "2.6",
+        ]));
+
+        let frames = unsafe { testing::decoded_frames(&output) };
+
+        std::fs::remove_file(&source).unwrap();
+        std::fs::remove_file(&output).unwrap();
+
+        assert_eq!(frames, 2 * testing::FRAME_RATE);
+    }
+
+    #[test]
+    fn cut_mp4_to_mp4() {
+        cut_mp4_to("mp4");
+    }
+
+    #[test]
+    fn cut_mp4_to_ts() {
+        cut_mp4_to("ts");
+    }
+}
@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ffmpeg_dev::sys;
+
+use crate::av::input_ctx::InputCtx;
+use crate::av::output_ctx::OutputCtx;
+use crate::av::stream_map::{MapSpec, StreamMap, StreamSelector};
+use crate::cmds::transmux;
+use crate::opts;
+
+pub fn run(args: opts::Dash) {
+    let input_path = args.input.as_str();
+
+    assert!(
+        PathBuf::from(input_path).exists(),
+        "file {} does not exist",
+        input_path
+    );
+
+    fs::create_dir_all(&args.output_dir).expect("could not create output directory");
+
+    let manifest = Path::new(&args.output_dir)
+        .join(&args.manifest)
+        .to_string_lossy()
+        .to_string();
+
+    unsafe {
+        let input = InputCtx::open(
+            input_path,
+            args.input_format.format.as_deref(),
+            &args.input_format.options(),
+        );
+        let mut output = OutputCtx::open(&manifest, Some("dash"));
+
+        let specs = [
+            MapSpec {
+                exclude: false,
+                selector: StreamSelector::Type(sys::AVMediaType_AVMEDIA_TYPE_VIDEO),
+            },
+            MapSpec {
+                exclude: false,
+                selector: StreamSelector::Type(sys::AVMediaType_AVMEDIA_TYPE_AUDIO),
+            },
+        ];
+
+        let map = StreamMap::new(&input, &specs, false);
+        map.print(&input);
+
+        // segment names are relative to the manifest, one init and a numbered series per stream
+        let muxer_opts = vec![
+            ("seg_duration", args.segment_duration.to_string()),
+            ("use_template", "1".to_string()),
+            ("use_timeline", "1".to_string()),
+            ("adaptation_sets", adaptation_sets(&input, &map)),
+            ("init_seg_name", "init-$RepresentationID$.m4s".to_string()),
+            (
+                "media_seg_name",
+                "chunk-$RepresentationID$-$Number%05d$.m4s".to_string(),
+            ),
+        ];
+
+        let mut muxer_opts: Vec<(String, String)> = muxer_opts
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect();
+
+        // user options come last, so they override the defaults above
+        muxer_opts.extend(opts::key_values(&args.muxer_opts));
+
+        let out_streams = transmux::add_streams(&input, &mut output, &map);
+        let mut pipeline = transmux::Pipeline {
+            filters: transmux::add_filters(&input, &output, &map, &out_streams, &[]),
+            ..Default::default()
+        };
+
+        output.open_file_with_options(&manifest, &muxer_opts);
+
+        transmux::copy_packets(&input, &mut output, &map, &mut pipeline);
+
+        sys::av_write_trailer(output.av);
+
+        println!("Wrote manifest {}", manifest);
+    }
+}
+
+// one adaptation set for all video representations and another for the audio ones
+// e.g. "id=0,streams=0 id=1,streams=1,2"
+unsafe fn adaptation_sets(input: &InputCtx, map: &StreamMap) -> String {
+    let mut video = Vec::new();
+    let mut audio = Vec::new();
+
+    for (output_index, &input_index) in map.inputs.iter().enumerate() {
+        let codec_type = (*(*input.get_stream(input_index)).codecpar).codec_type;
+
+        if codec_type == sys::AVMediaType_AVMEDIA_TYPE_VIDEO {
+            video.push(output_index.to_string());
+        } else {
+            audio.push(output_index.to_string());
+        }
+    }
+
+    let sets: Vec<String> = vec![video, audio]
+        .into_iter()
+        .filter(|streams| !streams.is_empty())
+        .enumerate()
+        .map(|(id, streams)| format!("id={},streams={}", id, streams.join(",")))
+        .collect();
+
+    sets.join(" ")
+}
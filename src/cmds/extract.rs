@@ -0,0 +1,132 @@
+use std::path::PathBuf;
+
+use ffmpeg_dev::sys;
+
+use crate::av::input_ctx::InputCtx;
+use crate::av::output_ctx::OutputCtx;
+use crate::av::stream_map::{self, MapSpec, StreamMap, StreamSelector};
+use crate::av::utils;
+use crate::cmds::transmux;
+use crate::opts;
+
+pub fn run(args: opts::Extract) {
+    let input_path = args.input.as_str();
+
+    assert!(
+        PathBuf::from(input_path).exists(),
+        "file {} does not exist",
+        input_path
+    );
+
+    unsafe {
+        let input = InputCtx::open(
+            input_path,
+            args.input_format.format.as_deref(),
+            &args.input_format.options(),
+        );
+
+        assert!(
+            args.stream < (*input.av).nb_streams as usize,
+            "input has no stream #{}",
+            args.stream
+        );
+
+        let in_stream = input.get_stream(args.stream);
+        let codecpar = (*in_stream).codecpar;
+
+        // attachments (e.g. fonts in mkv) have no packets, their contents are in the extradata
+        if (*codecpar).codec_type == sys::AVMediaType_AVMEDIA_TYPE_ATTACHMENT {
+            let path = args.output.clone().unwrap_or_else(|| {
+                stream_map::stream_tag(in_stream, "filename")
+                    .unwrap_or_else(|| format!("{}.bin", output_stem(input_path, args.stream)))
+            });
+
+            let data = utils::extradata(codecpar);
+            std::fs::write(&path, data).expect("could not write attachment");
+
+            println!("Wrote attachment {} ({} bytes)", path, data.len());
+            return;
+        }
+
+        // an explicit output picks its container by extension, e.g. .m4a instead of raw adts
+        let (mut output, output_path) = match &args.output {
+            Some(path) => (
+                OutputCtx::open(path, args.output_format.format.as_deref()),
+                path.clone(),
+            ),
+            None => {
+                let (muxer, extension) = natural_container((*codecpar).codec_id);
+                let path = format!("{}.{}", output_stem(input_path, args.stream), extension);
+
+                let muxer = args.output_format.format.as_deref().unwrap_or(muxer);
+
+                (OutputCtx::open(&path, Some(muxer)), path)
+            }
+        };
+
+        let specs = [MapSpec {
+            exclude: false,
+            selector: StreamSelector::Index(args.stream),
+        }];
+
+        let map = StreamMap::new(&input, &specs, false);
+        map.print(&input);
+
+        let out_streams = transmux::add_streams(&input, &mut output, &map);
+        let mut pipeline = transmux::Pipeline {
+            filters: transmux::add_filters(&input, &output, &map, &out_streams, &[]),
+            ..Default::default()
+        };
+
+        output.open_file_with_options(&output_path, &args.output_format.options());
+
+        transmux::copy_packets(&input, &mut output, &map, &mut pipeline);
+
+        sys::av_write_trailer(output.av);
+
+        println!("Wrote {}", output_path);
+    }
+}
+
+// muxer and file extension that hold a codec on its own
+unsafe fn natural_container(codec_id: sys::AVCodecID) -> (&'static str, &'static str) {
+    match codec_id {
+        sys::AVCodecID_AV_CODEC_ID_H264 => ("h264", "h264"),
+        sys::AVCodecID_AV_CODEC_ID_HEVC => ("hevc", "hevc"),
+        sys::AVCodecID_AV_CODEC_ID_MPEG2VIDEO => ("mpeg2video", "m2v"),
+        sys::AVCodecID_AV_CODEC_ID_VP8
+        | sys::AVCodecID_AV_CODEC_ID_VP9
+        | sys::AVCodecID_AV_CODEC_ID_AV1 => ("ivf", "ivf"),
+        sys::AVCodecID_AV_CODEC_ID_AAC => ("adts", "aac"),
+        sys::AVCodecID_AV_CODEC_ID_MP3 => ("mp3", "mp3"),
+        sys::AVCodecID_AV_CODEC_ID_AC3 => ("ac3", "ac3"),
+        sys::AVCodecID_AV_CODEC_ID_EAC3 => ("eac3", "eac3"),
+        sys::AVCodecID_AV_CODEC_ID_DTS => ("dts", "dts"),
+        sys::AVCodecID_AV_CODEC_ID_TRUEHD => ("truehd", "thd"),
+        sys::AVCodecID_AV_CODEC_ID_FLAC => ("flac", "flac"),
+        sys::AVCodecID_AV_CODEC_ID_OPUS | sys::AVCodecID_AV_CODEC_ID_VORBIS => ("ogg", "ogg"),
+        sys::AVCodecID_AV_CODEC_ID_PCM_S16LE
+        | sys::AVCodecID_AV_CODEC_ID_PCM_S24LE
+        | sys::AVCodecID_AV_CODEC_ID_PCM_S32LE
+        | sys::AVCodecID_AV_CODEC_ID_PCM_F32LE => ("wav", "wav"),
+        sys::AVCodecID_AV_CODEC_ID_SUBRIP => ("srt", "srt"),
+        sys::AVCodecID_AV_CODEC_ID_ASS | sys::AVCodecID_AV_CODEC_ID_SSA => ("ass", "ass"),
+        sys::AVCodecID_AV_CODEC_ID_WEBVTT => ("webvtt", "vtt"),
+        sys::AVCodecID_AV_CODEC_ID_HDMV_PGS_SUBTITLE => ("sup", "sup"),
+        codec_id => panic!(
+            "no standalone container known for {}, pass --output to pick one",
+            utils::c_str_to_string(sys::avcodec_get_name(codec_id))
+        ),
+    }
+}
+
+// e.g. movie.mkv, stream 2 -> movie.2
+fn output_stem(input_path: &str, stream: usize) -> String {
+    let stem = PathBuf::from(input_path)
+        .file_stem()
+        .map_or("output".to_string(), |stem| {
+            stem.to_string_lossy().to_string()
+        });
+
+    format!("{}.{}", stem, stream)
+}
@@ -0,0 +1,209 @@
+use std::path::PathBuf;
+use std::process;
+use std::ptr::null_mut;
+use std::slice;
+
+use ffmpeg_dev::sys;
+
+use crate::av::decoder::Decoder;
+use crate::av::input_ctx::InputCtx;
+use crate::av::utils;
+use crate::opts;
+
+pub fn run(args: opts::FrameHash) {
+    let input_path = args.input.as_str();
+
+    assert!(
+        PathBuf::from(input_path).exists(),
+        "file {} does not exist",
+        input_path
+    );
+
+    unsafe {
+        let lines = hash_file(input_path, &args.streams);
+
+        match &args.compare {
+            None => utils::write_report(&args.output, &(lines.join("\n") + "\n")),
+            Some(other_path) => {
+                assert!(
+                    PathBuf::from(other_path).exists(),
+                    "file {} does not exist",
+                    other_path
+                );
+
+                let other_lines = hash_file(other_path, &args.streams);
+                let (report, differences) = compare(&lines, &other_lines);
+
+                utils::write_report(&args.output, &report);
+
+                if differences > 0 {
+                    process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+// decodes every selected stream and hashes each frame, in the same layout as ffmpeg's framemd5
+unsafe fn hash_file(path: &str, streams: &[usize]) -> Vec<String> {
+    let input = InputCtx::new(path);
+    let in_streams = input.get_streams();
+
+    let mut lines = vec![
+        "#format: frame checksums".to_string(),
+        "#version: 2".to_string(),
+        "#hash: MD5".to_string(),
+    ];
+
+    let mut decoders = Vec::new();
+
+    for (i, in_stream) in in_streams.iter().enumerate() {
+        let codec_params = (**in_stream).codecpar;
+        let codec_type = (*codec_params).codec_type;
+
+        let selected = if streams.is_empty() {
+            codec_type == sys::AVMediaType_AVMEDIA_TYPE_VIDEO
+                || codec_type == sys::AVMediaType_AVMEDIA_TYPE_AUDIO
+        } else {
+            streams.contains(&i)
+        };
+
+        if !selected {
+            decoders.push(None);
+            continue;
+        }
+
+        let time_base = (**in_stream).time_base;
+        let media_type = utils::c_str_to_string(sys::av_get_media_type_string(codec_type));
+        let codec_name = utils::c_str_to_string(sys::avcodec_get_name((*codec_params).codec_id));
+
+        lines.push(format!("#tb {}: {}/{}", i, time_base.num, time_base.den));
+        lines.push(format!("#media_type {}: {}", i, media_type));
+        lines.push(format!("#codec_id {}: {}", i, codec_name));
+
+        decoders.push(Some(Decoder::new(*in_stream)));
+    }
+
+    lines.push("#stream#, dts, pts, duration, size, hash".to_string());
+
+    while sys::av_read_frame(input.av, input.packet) >= 0 {
+        let index = (*input.packet).stream_index as usize;
+
+        if let Some(Some(decoder)) = decoders.get_mut(index) {
+            decoder.send_packet(input.packet);
+
+            while decoder.receive_frame(input.frame) {
+                lines.push(hash_frame(index, input.frame));
+            }
+        }
+
+        sys::av_packet_unref(input.packet);
+    }
+
+    // drain frames still buffered in the decoders
+    for (index, decoder) in decoders.iter_mut().enumerate() {
+        if let Some(decoder) = decoder {
+            decoder.send_packet(null_mut());
+
+            while decoder.receive_frame(input.frame) {
+                lines.push(hash_frame(index, input.frame));
+            }
+        }
+    }
+
+    lines
+}
+
+unsafe fn hash_frame(index: usize, frame: *mut sys::AVFrame) -> String {
+    let data = frame_data(frame);
+
+    let mut digest = [0u8; 16];
+    sys::av_md5_sum(digest.as_mut_ptr(), data.as_ptr(), data.len() as i32);
+
+    let hash: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+
+    format!(
+        "{}, {:>10}, {:>10}, {:>8}, {:>8}, {}",
+        index,
+        (*frame).pkt_dts,
+        (*frame).best_effort_timestamp,
+        (*frame).pkt_duration,
+        data.len(),
+        hash
+    )
+}
+
+// raw pixel/sample data of a frame, without any padding, so that the hash only depends on content
+unsafe fn frame_data(frame: *mut sys::AVFrame) -> Vec<u8> {
+    if (*frame).nb_samples > 0 {
+        let format = (*frame).format;
+        let channels = (*frame).channels as usize;
+        let sample_size = sys::av_get_bytes_per_sample(format) as usize;
+        let samples = (*frame).nb_samples as usize;
+
+        let mut data = Vec::with_capacity(samples * sample_size * channels);
+
+        // planar audio has one plane per channel, packed audio interleaves them all in the first one
+        if sys::av_sample_fmt_is_planar(format) != 0 {
+            for channel in 0..channels {
+                let plane = *(*frame).extended_data.add(channel);
+                data.extend_from_slice(slice::from_raw_parts(plane, samples * sample_size));
+            }
+        } else {
+            let plane = *(*frame).extended_data;
+            data.extend_from_slice(slice::from_raw_parts(
+                plane,
+                samples * sample_size * channels,
+            ));
+        }
+
+        data
+    } else {
+        let format = (*frame).format;
+        let width = (*frame).width;
+        let height = (*frame).height;
+
+        let size = sys::av_image_get_buffer_size(format, width, height, 1);
+        let mut data = vec![0u8; size as usize];
+
+        sys::av_image_copy_to_buffer(
+            data.as_mut_ptr(),
+            size,
+            (*frame).data.as_ptr() as *const *const u8,
+            (*frame).linesize.as_ptr(),
+            format,
+            width,
+            height,
+            1,
+        );
+
+        data
+    }
+}
+
+// diffs two hash lists line by line, ignoring the headers
+fn compare(a: &[String], b: &[String]) -> (String, usize) {
+    let a: Vec<&String> = a.iter().filter(|l| !l.starts_with('#')).collect();
+    let b: Vec<&String> = b.iter().filter(|l| !l.starts_with('#')).collect();
+
+    let mut report = String::new();
+    let mut differences = 0;
+
+    for i in 0..a.len().max(b.len()) {
+        let left = a.get(i).map(|l| l.as_str()).unwrap_or("<missing>");
+        let right = b.get(i).map(|l| l.as_str()).unwrap_or("<missing>");
+
+        if left != right {
+            report.push_str(&format!("frame {}:\n- {}\n+ {}\n", i, left, right));
+            differences += 1;
+        }
+    }
+
+    report.push_str(&format!(
+        "{} of {} frames differ\n",
+        differences,
+        a.len().max(b.len())
+    ));
+
+    (report, differences)
+}
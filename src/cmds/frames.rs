@@ -19,7 +19,10 @@ pub fn run(args: opts::Frames) {
         ctx.open_video_stream(0);
 
         for _i in 0..args.number {
-            ctx.read_video_frame();
+            if !ctx.read_video_frame() {
+                break;
+            }
+
             debug_frame(&ctx);
             save_gray_frame(&ctx);
         }
@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ffmpeg_dev::sys;
+
+use crate::av::input_ctx::InputCtx;
+use crate::av::output_ctx::OutputCtx;
+use crate::av::stream_map::{MapSpec, StreamMap, StreamSelector};
+use crate::cmds::transmux;
+use crate::opts;
+
+pub fn run(args: opts::Hls) {
+    let input_path = args.input.as_str();
+
+    assert!(
+        PathBuf::from(input_path).exists(),
+        "file {} does not exist",
+        input_path
+    );
+
+    fs::create_dir_all(&args.output_dir).expect("could not create output directory");
+
+    let dir = Path::new(&args.output_dir);
+    let playlist = dir.join(&args.playlist).to_string_lossy().to_string();
+
+    let (segment_type, extension) = match args.segment_type {
+        opts::SegmentType::MpegTs => ("mpegts", "ts"),
+        opts::SegmentType::Fmp4 => ("fmp4", "m4s"),
+    };
+
+    let playlist_type = match args.playlist_type {
+        opts::PlaylistType::Vod => "vod",
+        opts::PlaylistType::Event => "event",
+    };
+
+    let segment_filename = dir.join(format!("segment_%05d.{}", extension));
+
+    // the hls muxer cuts segments on keyframes once the target duration is reached
+    let mut muxer_opts = vec![
+        ("hls_time", args.segment_duration.to_string()),
+        ("hls_list_size", "0".to_string()),
+        ("hls_playlist_type", playlist_type.to_string()),
+        ("hls_segment_type", segment_type.to_string()),
+        (
+            "hls_segment_filename",
+            segment_filename.to_string_lossy().to_string(),
+        ),
+        ("hls_flags", "independent_segments".to_string()),
+    ];
+
+    if let opts::SegmentType::Fmp4 = args.segment_type {
+        // relative to the playlist
+        muxer_opts.push(("hls_fmp4_init_filename", "init.mp4".to_string()));
+    }
+
+    let mut muxer_opts: Vec<(String, String)> = muxer_opts
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+
+    // user options come last, so they override the defaults above
+    muxer_opts.extend(opts::key_values(&args.muxer_opts));
+
+    unsafe {
+        let input = InputCtx::open(
+            input_path,
+            args.input_format.format.as_deref(),
+            &args.input_format.options(),
+        );
+        let mut output = OutputCtx::open(&playlist, Some("hls"));
+
+        // only audio and video go into the segments
+        let specs = [
+            MapSpec {
+                exclude: false,
+                selector: StreamSelector::Type(sys::AVMediaType_AVMEDIA_TYPE_VIDEO),
+            },
+            MapSpec {
+                exclude: false,
+                selector: StreamSelector::Type(sys::AVMediaType_AVMEDIA_TYPE_AUDIO),
+            },
+        ];
+
+        let map = StreamMap::new(&input, &specs, false);
+        map.print(&input);
+
+        let out_streams = transmux::add_streams(&input, &mut output, &map);
+        let mut pipeline = transmux::Pipeline {
+            filters: transmux::add_filters(&input, &output, &map, &out_streams, &[]),
+            ..Default::default()
+        };
+
+        output.open_file_with_options(&playlist, &muxer_opts);
+
+        transmux::copy_packets(&input, &mut output, &map, &mut pipeline);
+
+        sys::av_write_trailer(output.av);
+
+        println!("Wrote playlist {}", playlist);
+    }
+}
@@ -0,0 +1,93 @@
+use std::ptr::null_mut;
+
+use ffmpeg_dev::sys;
+
+use crate::av::decoder::Decoder;
+use crate::av::encoder::Encoder;
+use crate::av::input_ctx::InputCtx;
+use crate::av::output_ctx::OutputCtx;
+use crate::av::scaler::Scaler;
+use crate::av::utils;
+use crate::opts;
+
+pub fn run(args: opts::ImagesToVideo) {
+    let input_path = args.input.as_str();
+    let output_path = args.output.as_str();
+
+    unsafe {
+        let framerate = utils::parse_video_rate(&args.framerate);
+
+        // the image2 demuxer handles both printf-style patterns (img%03d.png) and globs (*.png)
+        let mut demuxer_opts = vec![("framerate".to_string(), args.framerate.clone())];
+
+        if input_path.contains('*') || input_path.contains('?') {
+            demuxer_opts.push(("pattern_type".to_string(), "glob".to_string()));
+        }
+
+        let input = InputCtx::open(input_path, Some("image2"), &demuxer_opts);
+        let in_stream = input.get_stream(0);
+        let mut decoder = Decoder::new(in_stream);
+
+        let mut output = OutputCtx::open(output_path, args.output_format.format.as_deref());
+        let mut encoder = Encoder::new(args.vcodec.as_str());
+
+        // most encoders (and yuv420p) require even dimensions
+        let width = (*(*in_stream).codecpar).width & !1;
+        let height = (*(*in_stream).codecpar).height & !1;
+
+        let pix_fmt = match &args.pix_fmt {
+            Some(name) => utils::parse_pix_fmt(name),
+            None => encoder.default_pix_fmt(),
+        };
+
+        (*encoder.codec_ctx).width = width;
+        (*encoder.codec_ctx).height = height;
+        (*encoder.codec_ctx).pix_fmt = pix_fmt;
+        (*encoder.codec_ctx).framerate = framerate;
+        (*encoder.codec_ctx).time_base = utils::av_inv_q(framerate);
+
+        encoder.open(&output);
+        let out_stream = encoder.new_stream(&mut output);
+
+        output.open_file_with_options(output_path, &args.output_format.options());
+
+        let mut scaler = Scaler::new();
+        let mut enc_frame = utils::alloc_frame(width, height, pix_fmt);
+        let mut frame_count = 0;
+
+        loop {
+            let more = sys::av_read_frame(input.av, input.packet) >= 0;
+
+            if more {
+                decoder.send_packet(input.packet);
+                sys::av_packet_unref(input.packet);
+            } else {
+                decoder.send_packet(null_mut());
+            }
+
+            while decoder.receive_frame(input.frame) {
+                // each image is one frame, so the frame counter is the pts in 1/framerate units
+                scaler.scale(input.frame, enc_frame);
+                (*enc_frame).pts = frame_count;
+                frame_count += 1;
+
+                encoder.send_frame(enc_frame);
+                encoder.write_packets(&mut output, (*out_stream).index);
+            }
+
+            if !more {
+                break;
+            }
+        }
+
+        // flush the encoder
+        encoder.send_frame(null_mut());
+        encoder.write_packets(&mut output, (*out_stream).index);
+
+        sys::av_write_trailer(output.av);
+
+        println!("Encoded {} images into {}", frame_count, output_path);
+
+        sys::av_frame_free(&mut enc_frame);
+    }
+}
@@ -1,5 +1,18 @@
+pub mod concat;
+pub mod contact_sheet;
+pub mod cut;
+pub mod dash;
+pub mod extract;
 pub mod formats;
+pub mod framehash;
 pub mod frames;
+pub mod hls;
+pub mod images_to_video;
+pub mod mux;
+pub mod qc;
 pub mod remux;
+pub mod scenes;
+pub mod split;
 pub mod transcode;
 pub mod transmux;
+pub mod waveform;
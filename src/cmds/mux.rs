@@ -0,0 +1,199 @@
+use std::path::PathBuf;
+use std::ptr::null_mut;
+
+use ffmpeg_dev::sys;
+
+use crate::av::input_ctx::InputCtx;
+use crate::av::output_ctx::OutputCtx;
+use crate::av::stream_map::{MapSpec, StreamMap};
+use crate::av::utils;
+use crate::opts;
+
+// an input together with the streams it contributes
+struct Source {
+    input: InputCtx,
+    map: StreamMap,
+    // output index of the first mapped stream
+    first_output: usize,
+    // in AV_TIME_BASE units
+    offset: i64,
+    // whether `input.packet` holds a packet that wasn't written yet
+    pending: bool,
+}
+
+impl Source {
+    // reads the next mapped packet, shifted by the input offset
+    unsafe fn read(&mut self) {
+        self.pending = false;
+
+        while sys::av_read_frame(self.input.av, self.input.packet) >= 0 {
+            let packet = self.input.packet;
+            let index = (*packet).stream_index as usize;
+
+            if self.map.output_index(index).is_none() {
+                sys::av_packet_unref(packet);
+                continue;
+            }
+
+            let shift = sys::av_rescale_q(
+                self.offset,
+                utils::av_time_base_q(),
+                (*self.input.get_stream(index)).time_base,
+            );
+
+            if (*packet).pts != utils::AV_NOPTS_VALUE {
+                (*packet).pts += shift;
+            }
+
+            if (*packet).dts != utils::AV_NOPTS_VALUE {
+                (*packet).dts += shift;
+            }
+
+            self.pending = true;
+            return;
+        }
+    }
+
+    // dts of the pending packet in AV_TIME_BASE units, packets without one go first
+    unsafe fn next_dts(&self) -> i64 {
+        let packet = self.input.packet;
+
+        if (*packet).dts == utils::AV_NOPTS_VALUE {
+            return i64::MIN;
+        }
+
+        let time_base = (*self.input.get_stream((*packet).stream_index as usize)).time_base;
+
+        sys::av_rescale_q((*packet).dts, time_base, utils::av_time_base_q())
+    }
+}
+
+pub fn run(args: opts::Mux) {
+    for path in args.inputs.iter() {
+        assert!(PathBuf::from(path).exists(), "file {} does not exist", path);
+    }
+
+    for spec in args.map.iter() {
+        assert!(
+            spec.input < args.inputs.len(),
+            "--map refers to input {}, but there are only {} inputs",
+            spec.input,
+            args.inputs.len()
+        );
+    }
+
+    for offset in args.offsets.iter() {
+        assert!(
+            offset.input < args.inputs.len(),
+            "--offset refers to input {}, but there are only {} inputs",
+            offset.input,
+            args.inputs.len()
+        );
+    }
+
+    unsafe {
+        let mut output =
+            OutputCtx::open(args.output.as_str(), args.output_format.format.as_deref());
+        let mut sources: Vec<Source> = Vec::new();
+
+        for (i, path) in args.inputs.iter().enumerate() {
+            let specs: Vec<MapSpec> = args
+                .map
+                .iter()
+                .filter(|spec| spec.input == i)
+                .map(|spec| spec.spec.clone())
+                .collect();
+
+            // with explicit maps, inputs without any contribute nothing
+            if !args.map.is_empty() && specs.is_empty() {
+                println!("Input #{} ({}): no streams mapped, skipping", i, path);
+                continue;
+            }
+
+            let input = InputCtx::open(
+                path,
+                args.input_format.format.as_deref(),
+                &args.input_format.options(),
+            );
+            let map = StreamMap::new(&input, &specs, false);
+
+            let offset = args
+                .offsets
+                .iter()
+                .rev()
+                .find(|offset| offset.input == i)
+                .map_or(0, |offset| utils::parse_time(&offset.offset));
+
+            let first_output = (*output.av).nb_streams as usize;
+
+            println!(
+                "Input #{} ({}), offset {}s:",
+                i,
+                path,
+                offset as f64 / sys::AV_TIME_BASE as f64
+            );
+
+            for (output_index, &input_index) in map.inputs.iter().enumerate() {
+                let in_stream = input.get_stream(input_index);
+                let codecpar = (*in_stream).codecpar;
+
+                println!(
+                    "  Stream #{}:{} -> #{} ({} {})",
+                    i,
+                    input_index,
+                    first_output + output_index,
+                    utils::c_str_to_string(sys::av_get_media_type_string((*codecpar).codec_type)),
+                    utils::c_str_to_string(sys::avcodec_get_name((*codecpar).codec_id))
+                );
+
+                let out_stream = sys::avformat_new_stream(output.av, null_mut());
+                assert!(out_stream != null_mut(), "failed to allocate output stream");
+
+                let response = sys::avcodec_parameters_copy((*out_stream).codecpar, codecpar);
+                utils::check_error(response);
+
+                (*(*out_stream).codecpar).codec_tag = 0;
+            }
+
+            sources.push(Source {
+                input,
+                map,
+                first_output,
+                offset,
+                pending: false,
+            });
+        }
+
+        output.open_file_with_options(args.output.as_str(), &args.output_format.options());
+
+        for source in sources.iter_mut() {
+            source.read();
+        }
+
+        // always write the pending packet with the lowest dts, so the output is interleaved
+        // across inputs instead of one input after the other
+        loop {
+            let next = sources
+                .iter_mut()
+                .filter(|source| source.pending)
+                .min_by_key(|source| source.next_dts());
+
+            let source = match next {
+                Some(source) => source,
+                None => break,
+            };
+
+            let packet = source.input.packet;
+            let index = (*packet).stream_index as usize;
+            let time_base = (*source.input.get_stream(index)).time_base;
+
+            (*packet).stream_index =
+                (source.first_output + source.map.output_index(index).unwrap()) as i32;
+            output.write_packet(packet, time_base);
+
+            source.read();
+        }
+
+        sys::av_write_trailer(output.av);
+    }
+}
@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+
+use ffmpeg_dev::sys;
+
+use crate::av::input_ctx::InputCtx;
+use crate::av::scaler::Scaler;
+use crate::av::utils;
+use crate::opts;
+
+struct FrameStats {
+    time: f64,
+    mean: f64,
+    min: u8,
+    max: u8,
+    // fraction of pixels below the black threshold
+    black: f64,
+    // mean absolute difference to the previous frame, normalized to [0, 1]
+    difference: f64,
+}
+
+// collects the time ranges during which a condition holds for at least `min_duration`
+struct Segments {
+    min_duration: f64,
+    start: Option<f64>,
+    ranges: Vec<(f64, f64)>,
+}
+
+impl Segments {
+    fn new(min_duration: f64) -> Segments {
+        Segments {
+            min_duration,
+            start: None,
+            ranges: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, active: bool, time: f64) {
+        match (active, self.start) {
+            (true, None) => self.start = Some(time),
+            (false, Some(_)) => self.close(time),
+            _ => {}
+        }
+    }
+
+    fn close(&mut self, time: f64) {
+        if let Some(start) = self.start.take() {
+            if time - start >= self.min_duration {
+                self.ranges.push((start, time));
+            }
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let entries: Vec<String> = self
+            .ranges
+            .iter()
+            .map(|(start, end)| {
+                format!(
+                    "    {{ \"start\": {:.3}, \"end\": {:.3}, \"duration\": {:.3} }}",
+                    start,
+                    end,
+                    end - start
+                )
+            })
+            .collect();
+
+        format!("[\n{}\n  ]", entries.join(",\n"))
+    }
+}
+
+pub fn run(args: opts::Qc) {
+    let path = args.input.as_str();
+
+    assert!(PathBuf::from(path).exists(), "file {} does not exist", path);
+
+    unsafe {
+        let mut ctx = InputCtx::new(path);
+
+        let index = ctx.find_video_stream();
+        ctx.open_video_stream(index);
+
+        let time_base = (*ctx.get_stream(index as usize)).time_base;
+        let (start, _) = ctx.stream_range(index as usize);
+
+        let width = (*ctx.codec).width;
+        let height = (*ctx.codec).height;
+
+        let mut scaler = Scaler::new();
+        let mut luma_frame = utils::alloc_frame(width, height, sys::AVPixelFormat_AV_PIX_FMT_GRAY8);
+
+        let mut black = Segments::new(args.black_min_duration);
+        let mut frozen = Segments::new(args.freeze_min_duration);
+        let mut stats = Vec::new();
+        let mut previous: Option<Vec<u8>> = None;
+        let mut end = 0.0;
+
+        while ctx.read_video_frame() {
+            let pts = (*ctx.frame).best_effort_timestamp;
+            let time = (pts - start) as f64 * utils::av_q2d(time_base);
+
+            // converting to gray8 gives the luma plane regardless of the source pixel format
+            scaler.scale(ctx.frame, luma_frame);
+            let luma = utils::copy_plane(luma_frame, 0, width, height);
+
+            let frame_stats = compute_stats(time, &luma, previous.as_deref(), args.black_threshold);
+
+            black.update(frame_stats.black >= args.black_ratio, time);
+            let is_frozen = previous.is_some() && frame_stats.difference <= args.freeze_threshold;
+
+            // a freeze starts at the frame being repeated, not at its first copy
+            let freeze_time = if is_frozen && frozen.start.is_none() {
+                end_of_previous(&stats, time)
+            } else {
+                time
+            };
+
+            frozen.update(is_frozen, freeze_time);
+
+            end = time + (*ctx.frame).pkt_duration as f64 * utils::av_q2d(time_base);
+            previous = Some(luma);
+            stats.push(frame_stats);
+        }
+
+        black.close(end);
+        frozen.close(end);
+
+        sys::av_frame_free(&mut luma_frame);
+
+        let mut report = format!(
+            "{{\n  \"duration\": {:.3},\n  \"frames\": {},\n  \"black\": {},\n  \"frozen\": {}",
+            end,
+            stats.len(),
+            black.to_json(),
+            frozen.to_json()
+        );
+
+        if args.frame_stats {
+            report.push_str(&format!(",\n  \"frame_stats\": {}", stats_to_json(&stats)));
+        }
+
+        report.push_str("\n}\n");
+
+        utils::write_report(&args.output, &report);
+    }
+}
+
+fn compute_stats(
+    time: f64,
+    luma: &[u8],
+    previous: Option<&[u8]>,
+    black_threshold: u8,
+) -> FrameStats {
+    let mut sum: u64 = 0;
+    let mut min = u8::max_value();
+    let mut max = 0;
+    let mut black_pixels = 0;
+
+    for &pixel in luma {
+        sum += pixel as u64;
+        min = min.min(pixel);
+        max = max.max(pixel);
+
+        if pixel <= black_threshold {
+            black_pixels += 1;
+        }
+    }
+
+    let difference = match previous {
+        Some(previous) => utils::luma_difference(luma, previous),
+        None => 0.0,
+    };
+
+    FrameStats {
+        time,
+        mean: sum as f64 / luma.len() as f64,
+        min,
+        max,
+        black: black_pixels as f64 / luma.len() as f64,
+        difference,
+    }
+}
+
+// timestamp of the last frame seen before `time`
+fn end_of_previous(stats: &[FrameStats], time: f64) -> f64 {
+    stats.last().map(|s| s.time).unwrap_or(time)
+}
+
+fn stats_to_json(stats: &[FrameStats]) -> String {
+    let entries: Vec<String> = stats
+        .iter()
+        .map(|s| {
+            format!(
+                "    {{ \"time\": {:.3}, \"mean\": {:.2}, \"min\": {}, \"max\": {}, \"black\": {:.4}, \"difference\": {:.5} }}",
+                s.time, s.mean, s.min, s.max, s.black, s.difference
+            )
+        })
+        .collect();
+
+    format!("[\n{}\n  ]", entries.join(",\n"))
+}
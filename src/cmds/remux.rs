@@ -5,9 +5,327 @@ use ffmpeg_dev::sys;
 
 use crate::av::input_ctx::InputCtx;
 use crate::av::output_ctx::OutputCtx;
+use crate::av::preflight::Preflight;
+use crate::av::progress::Progress;
+use crate::av::stream_map::{self, StreamMap};
+use crate::av::subtitle::{self, SubtitleConverter};
+use crate::av::timestamps::TimestampFixer;
 use crate::av::utils;
+use crate::cmds::transmux;
 use crate::opts;
 
+// keyframe-aligned time range selection
+// all timestamps are in AV_TIME_BASE units, relative to the input start
+struct Trim {
+    start: i64,
+    end: Option<i64>,
+    input_start: i64,
+    // stream whose first keyframe defines the actual start (video, if any)
+    reference: usize,
+    // actual start, once the reference keyframe was found
+    offset: Option<i64>,
+    finished: Vec<bool>,
+}
+
+impl Trim {
+    unsafe fn new(args: &opts::Remux, input: &InputCtx, map: &StreamMap) -> Option<Trim> {
+        if args.start.is_none() && args.end.is_none() && args.duration.is_none() {
+            return None;
+        }
+
+        let start = args.start.as_ref().map_or(0, |s| utils::parse_time(s));
+        let end = match (&args.end, &args.duration) {
+            (Some(_), Some(_)) => panic!("--end and --duration are mutually exclusive"),
+            (Some(end), None) => Some(utils::parse_time(end)),
+            (None, Some(duration)) => Some(start + utils::parse_time(duration)),
+            (None, None) => None,
+        };
+
+        if let Some(end) = end {
+            assert!(end > start, "end must be after start");
+        }
+
+        let input_start = if (*input.av).start_time == utils::AV_NOPTS_VALUE {
+            0
+        } else {
+            (*input.av).start_time
+        };
+
+        let reference = *map
+            .inputs
+            .iter()
+            .find(|&&i| {
+                (*(*input.get_stream(i)).codecpar).codec_type == sys::AVMediaType_AVMEDIA_TYPE_VIDEO
+            })
+            .unwrap_or(&map.inputs[0]);
+
+        Some(Trim {
+            start,
+            end,
+            input_start,
+            reference,
+            offset: None,
+            finished: vec![false; map.outputs.len()],
+        })
+    }
+
+    // seeks to the keyframe at or before the requested start
+    unsafe fn seek(&self, input: &mut InputCtx) {
+        if self.start == 0 {
+            return;
+        }
+
+        let response = sys::av_seek_frame(
+            input.av,
+            -1,
+            self.input_start + self.start,
+            sys::AVSEEK_FLAG_BACKWARD as i32,
+        );
+
+        utils::check_error(response);
+    }
+
+    // decides whether a packet is kept, rebasing its timestamps to zero if so
+    unsafe fn filter(&mut self, packet: *mut sys::AVPacket, in_stream: *mut sys::AVStream) -> bool {
+        let index = (*packet).stream_index as usize;
+        let time_base = (*in_stream).time_base;
+
+        let ts = if (*packet).pts != utils::AV_NOPTS_VALUE {
+            (*packet).pts
+        } else {
+            (*packet).dts
+        };
+
+        if ts == utils::AV_NOPTS_VALUE {
+            return self.offset.is_some() && !self.finished[index];
+        }
+
+        let time = sys::av_rescale_q(ts, time_base, utils::av_time_base_q()) - self.input_start;
+
+        if let Some(end) = self.end {
+            if time >= end {
+                self.finished[index] = true;
+            }
+        }
+
+        if self.finished[index] {
+            return false;
+        }
+
+        let offset = match self.offset {
+            Some(offset) => offset,
+            // nothing is written until the reference stream reaches a keyframe
+            None if index == self.reference
+                && (*packet).flags & sys::AV_PKT_FLAG_KEY as i32 != 0 =>
+            {
+                self.offset = Some(time);
+                time
+            }
+            None => return false,
+        };
+
+        // the reference stream keeps everything after its keyframe, as later packets depend on it
+        if index != self.reference && time < offset {
+            return false;
+        }
+
+        let rebase = sys::av_rescale_q(
+            offset + self.input_start,
+            utils::av_time_base_q(),
+            time_base,
+        );
+
+        if (*packet).pts != utils::AV_NOPTS_VALUE {
+            (*packet).pts -= rebase;
+        }
+
+        if (*packet).dts != utils::AV_NOPTS_VALUE {
+            (*packet).dts -= rebase;
+        }
+
+        true
+    }
+
+    fn done(&self, map: &StreamMap) -> bool {
+        map.inputs.iter().all(|&i| self.finished[i])
+    }
+
+    fn report(&self) {
+        let requested = utils::format_timestamp(self.start as f64 / sys::AV_TIME_BASE as f64);
+
+        match self.offset {
+            Some(offset) => println!(
+                "Requested start {}, actual start {} (nearest preceding keyframe)",
+                requested,
+                utils::format_timestamp(offset as f64 / sys::AV_TIME_BASE as f64)
+            ),
+            None => println!("Requested start {}, no keyframe found in range", requested),
+        }
+    }
+}
+
+// a subtitle file muxed in as an additional track
+struct ExternalSubtitle {
+    input: InputCtx,
+    stream: usize,
+    out_index: usize,
+    // set when the container doesn't take the file's codec as is
+    converter: Option<SubtitleConverter>,
+    // whether `input.packet` holds a packet that wasn't written yet
+    pending: bool,
+}
+
+impl ExternalSubtitle {
+    unsafe fn new(spec: &opts::SubtitleSpec, output: &mut OutputCtx) -> ExternalSubtitle {
+        assert!(
+            PathBuf::from(&spec.path).exists(),
+            "file {} does not exist",
+            spec.path
+        );
+
+        let input = InputCtx::new(&spec.path);
+        let stream = input
+            .find_stream(sys::AVMediaType_AVMEDIA_TYPE_SUBTITLE)
+            .unwrap_or_else(|| panic!("no subtitle stream in {}", spec.path))
+            as usize;
+
+        let in_stream = input.get_stream(stream);
+        let codec_id = (*(*in_stream).codecpar).codec_id;
+        let out_codec_id = subtitle::output_codec(codec_id, output);
+
+        let out_stream = sys::avformat_new_stream(output.av, null_mut());
+        assert!(out_stream != null_mut(), "failed to allocate output stream");
+
+        let converter = if out_codec_id == codec_id {
+            let response =
+                sys::avcodec_parameters_copy((*out_stream).codecpar, (*in_stream).codecpar);
+            utils::check_error(response);
+
+            (*(*out_stream).codecpar).codec_tag = 0;
+            (*out_stream).time_base = (*in_stream).time_base;
+
+            None
+        } else {
+            Some(SubtitleConverter::new(
+                in_stream,
+                out_codec_id,
+                output,
+                out_stream,
+            ))
+        };
+
+        if let Some(language) = &spec.language {
+            stream_map::set_stream_language(out_stream, language);
+        }
+
+        if spec.default {
+            (*out_stream).disposition |= sys::AV_DISPOSITION_DEFAULT as i32;
+        }
+
+        if spec.forced {
+            (*out_stream).disposition |= sys::AV_DISPOSITION_FORCED as i32;
+        }
+
+        println!(
+            "Subtitle {} -> #{} ({} -> {})",
+            spec.path,
+            (*out_stream).index,
+            utils::c_str_to_string(sys::avcodec_get_name(codec_id)),
+            utils::c_str_to_string(sys::avcodec_get_name(out_codec_id))
+        );
+
+        let mut external = ExternalSubtitle {
+            input,
+            stream,
+            out_index: (*out_stream).index as usize,
+            converter,
+            pending: false,
+        };
+
+        external.read();
+        external
+    }
+
+    unsafe fn read(&mut self) {
+        self.pending = false;
+
+        while sys::av_read_frame(self.input.av, self.input.packet) >= 0 {
+            if (*self.input.packet).stream_index as usize == self.stream {
+                self.pending = true;
+                return;
+            }
+
+            sys::av_packet_unref(self.input.packet);
+        }
+    }
+
+    // writes the pending packets up to `time`, all times in AV_TIME_BASE units
+    // `shift` moves the subtitles onto the output timeline, packets that end up before zero or
+    // after `end` (subtitle timeline) are dropped
+    unsafe fn write_until(
+        &mut self,
+        time: i64,
+        shift: i64,
+        end: Option<i64>,
+        output: &mut OutputCtx,
+    ) {
+        let time_base = (*self.input.get_stream(self.stream)).time_base;
+
+        while self.pending {
+            let packet = self.input.packet;
+
+            if (*packet).pts == utils::AV_NOPTS_VALUE {
+                sys::av_packet_unref(packet);
+                self.read();
+                continue;
+            }
+
+            let packet_time = sys::av_rescale_q((*packet).pts, time_base, utils::av_time_base_q());
+
+            if end.map_or(false, |end| packet_time >= end) {
+                sys::av_packet_unref(packet);
+                self.pending = false;
+                return;
+            }
+
+            if packet_time - shift > time {
+                return;
+            }
+
+            if packet_time >= shift {
+                let rebase = sys::av_rescale_q(shift, utils::av_time_base_q(), time_base);
+
+                (*packet).pts -= rebase;
+
+                if (*packet).dts != utils::AV_NOPTS_VALUE {
+                    (*packet).dts -= rebase;
+                }
+
+                match self.converter.as_mut() {
+                    Some(converter) => converter.write(packet, output, self.out_index),
+                    None => {
+                        (*packet).stream_index = self.out_index as i32;
+                        output.write_packet(packet, time_base);
+                    }
+                }
+            }
+
+            sys::av_packet_unref(packet);
+            self.read();
+        }
+    }
+}
+
+// subtitle files start at zero: without trimming the output keeps the input timestamps, so they
+// move to the input start, with trimming they move along with the actual start
+unsafe fn subtitle_range(trim: &Option<Trim>, input: &InputCtx) -> (i64, Option<i64>) {
+    match trim {
+        Some(trim) => (trim.offset.unwrap_or(0), trim.end),
+        None if (*input.av).start_time == utils::AV_NOPTS_VALUE => (0, None),
+        None => (-(*input.av).start_time, None),
+    }
+}
+
 pub fn run(args: opts::Remux) {
     let input_path = args.input.as_str();
     let output_path = args.output.as_str();
@@ -19,13 +337,24 @@ pub fn run(args: opts::Remux) {
     );
 
     unsafe {
-        let mut input = InputCtx::new(input_path);
-        let mut output = OutputCtx::new(output_path);
+        let mut input = InputCtx::open(
+            input_path,
+            args.input_format.format.as_deref(),
+            &args.input_format.options(),
+        );
+        let mut output = OutputCtx::open(output_path, args.output_format.format.as_deref());
 
         let in_streams = input.get_streams();
         let mut out_streams = Vec::new();
 
-        for i in 0..in_streams.len() {
+        let mut map = StreamMap::new(&input, &args.map, args.drop_data);
+        map.print(&input);
+
+        let preflight = Preflight::new(&input, &output, &map);
+        preflight.print(&input, &output);
+        preflight.check(&mut map, args.auto);
+
+        for &i in map.inputs.iter() {
             let in_stream = in_streams[i];
 
             let out_stream = sys::avformat_new_stream(output.av, null_mut());
@@ -38,7 +367,41 @@ pub fn run(args: opts::Remux) {
             out_streams.push(out_stream);
         }
 
-        output.open_file(output_path);
+        let mut transcoders =
+            transmux::add_transcoders(&input, &output, &map, &out_streams, &preflight);
+
+        let mut subtitles: Vec<ExternalSubtitle> = args
+            .subtitles
+            .iter()
+            .map(|spec| ExternalSubtitle::new(spec, &mut output))
+            .collect();
+
+        // tagged once every output stream exists, external subtitles included
+        let stream_count = (*output.av).nb_streams as usize;
+        stream_map::check_output_tags(stream_count, &args.dispositions, &args.languages);
+
+        for index in 0..stream_count {
+            let out_stream = *(*output.av).streams.add(index);
+            stream_map::tag_output_stream(out_stream, index, &args.dispositions, &args.languages);
+        }
+
+        let mut trim = Trim::new(&args, &input, &map);
+
+        let mut fixer = if args.fix_timestamps {
+            Some(TimestampFixer::new(&input))
+        } else {
+            None
+        };
+
+        if let Some(trim) = &trim {
+            trim.seek(&mut input);
+        }
+
+        output.set_mp4_mode(args.mp4_mode.as_ref(), args.frag_duration);
+        output.open_file_with_options(output_path, &args.output_format.options());
+
+        let (progress_start, progress_end) = trim.as_ref().map_or((0, None), |t| (t.start, t.end));
+        let mut progress = Progress::new(&args.progress, &input, progress_start, progress_end);
 
         loop {
             let ret = sys::av_read_frame(input.av, input.packet);
@@ -48,16 +411,60 @@ pub fn run(args: opts::Remux) {
                 break;
             }
 
+            progress.update(&input, &output);
+
             let index = (*input.packet).stream_index as usize;
 
-            if index >= out_streams.len() {
+            let out_index = match map.output_index(index) {
+                Some(out_index) => out_index,
+                None => {
+                    sys::av_packet_unref(input.packet);
+                    continue;
+                }
+            };
+
+            let in_stream = in_streams[index];
+            let out_stream = out_streams[out_index];
+
+            if let Some(fixer) = fixer.as_mut() {
+                fixer.fix(input.packet);
+            }
+
+            if let Some(trim) = trim.as_mut() {
+                if !trim.filter(input.packet, in_stream) {
+                    sys::av_packet_unref(input.packet);
+
+                    if trim.done(&map) {
+                        break;
+                    }
+
+                    continue;
+                }
+            }
+
+            // keep the subtitle tracks interleaved with the main streams
+            let ts = if (*input.packet).dts != utils::AV_NOPTS_VALUE {
+                (*input.packet).dts
+            } else {
+                (*input.packet).pts
+            };
+
+            if ts != utils::AV_NOPTS_VALUE {
+                let time = sys::av_rescale_q(ts, (*in_stream).time_base, utils::av_time_base_q());
+
+                let (shift, end) = subtitle_range(&trim, &input);
+
+                for external in subtitles.iter_mut() {
+                    external.write_until(time, shift, end, &mut output);
+                }
+            }
+
+            if let Some(transcoder) = transcoders[out_index].as_mut() {
+                transcoder.send_packet(input.packet, &mut output, out_index);
                 sys::av_packet_unref(input.packet);
                 continue;
             }
 
-            let in_stream = in_streams[index];
-            let out_stream = out_streams[index];
-
             (*input.packet).pts = sys::av_rescale_q_rnd(
                 (*input.packet).pts,
                 (*in_stream).time_base,
@@ -76,6 +483,7 @@ pub fn run(args: opts::Remux) {
                 (*out_stream).time_base,
             );
             (*input.packet).pos = -1;
+            (*input.packet).stream_index = out_index as i32;
 
             let ret = sys::av_interleaved_write_frame(output.av, input.packet);
 
@@ -84,6 +492,27 @@ pub fn run(args: opts::Remux) {
             sys::av_packet_unref(input.packet);
         }
 
+        for (out_index, transcoder) in transcoders.iter_mut().enumerate() {
+            if let Some(transcoder) = transcoder {
+                transcoder.flush(&mut output, out_index);
+            }
+        }
+
+        let (shift, end) = subtitle_range(&trim, &input);
+
+        for external in subtitles.iter_mut() {
+            external.write_until(i64::MAX, shift, end, &mut output);
+        }
+
         sys::av_write_trailer(output.av);
+        progress.finish(&output);
+
+        if let Some(fixer) = &fixer {
+            fixer.report(&input);
+        }
+
+        if let Some(trim) = &trim {
+            trim.report();
+        }
     }
 }
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use ffmpeg_dev::sys;
+
+use crate::av::input_ctx::InputCtx;
+use crate::av::scaler::Scaler;
+use crate::av::utils;
+use crate::opts;
+
+// frames are compared on a small grayscale thumbnail,
+// which is fast and ignores noise and compression artifacts
+const SAMPLE_WIDTH: i32 = 64;
+const SAMPLE_HEIGHT: i32 = 36;
+
+struct Scene {
+    start: f64,
+    end: f64,
+    score: f64,
+}
+
+pub fn run(args: opts::Scenes) {
+    let path = args.input.as_str();
+
+    assert!(PathBuf::from(path).exists(), "file {} does not exist", path);
+
+    unsafe {
+        let mut ctx = InputCtx::new(path);
+
+        let index = ctx.find_video_stream();
+        ctx.open_video_stream(index);
+
+        let time_base = (*ctx.get_stream(index as usize)).time_base;
+        let (start, _) = ctx.stream_range(index as usize);
+
+        let mut scaler = Scaler::new();
+        let mut sample = utils::alloc_frame(
+            SAMPLE_WIDTH,
+            SAMPLE_HEIGHT,
+            sys::AVPixelFormat_AV_PIX_FMT_GRAY8,
+        );
+
+        let mut scenes = Vec::new();
+        let mut previous: Option<Vec<u8>> = None;
+        let mut scene_start = 0.0;
+        let mut scene_score = 0.0;
+        let mut last_time = 0.0;
+        let mut frames = 0;
+
+        while ctx.read_video_frame() {
+            let pts = (*ctx.frame).best_effort_timestamp;
+            let time = (pts - start) as f64 * utils::av_q2d(time_base);
+
+            scaler.scale(ctx.frame, sample);
+            let luma = utils::copy_plane(sample, 0, SAMPLE_WIDTH, SAMPLE_HEIGHT);
+
+            if let Some(previous) = &previous {
+                let score = utils::luma_difference(previous, &luma);
+
+                if score > args.threshold && time - scene_start >= args.min_scene_length {
+                    scenes.push(Scene {
+                        start: scene_start,
+                        end: time,
+                        score: scene_score,
+                    });
+
+                    scene_start = time;
+                    scene_score = score;
+                }
+            }
+
+            previous = Some(luma);
+            last_time = time + frame_duration(&ctx, time_base);
+            frames += 1;
+        }
+
+        if frames > 0 {
+            scenes.push(Scene {
+                start: scene_start,
+                end: last_time,
+                score: scene_score,
+            });
+        }
+
+        sys::av_frame_free(&mut sample);
+
+        let report = match args.format {
+            opts::ReportFormat::Json => to_json(&scenes),
+            opts::ReportFormat::Csv => to_csv(&scenes),
+        };
+
+        utils::write_report(&args.output, &report);
+    }
+}
+
+unsafe fn frame_duration(ctx: &InputCtx, time_base: sys::AVRational) -> f64 {
+    (*ctx.frame).pkt_duration as f64 * utils::av_q2d(time_base)
+}
+
+fn to_json(scenes: &[Scene]) -> String {
+    let entries: Vec<String> = scenes
+        .iter()
+        .enumerate()
+        .map(|(i, scene)| {
+            format!(
+                "    {{ \"index\": {}, \"start\": {:.3}, \"end\": {:.3}, \"duration\": {:.3}, \"score\": {:.4} }}",
+                i,
+                scene.start,
+                scene.end,
+                scene.end - scene.start,
+                scene.score
+            )
+        })
+        .collect();
+
+    format!("{{\n  \"scenes\": [\n{}\n  ]\n}}\n", entries.join(",\n"))
+}
+
+fn to_csv(scenes: &[Scene]) -> String {
+    let mut csv = String::from("index,start,end,duration,score\n");
+
+    for (i, scene) in scenes.iter().enumerate() {
+        csv.push_str(&format!(
+            "{},{:.3},{:.3},{:.3},{:.4}\n",
+            i,
+            scene.start,
+            scene.end,
+            scene.end - scene.start,
+            scene.score
+        ));
+    }
+
+    csv
+}
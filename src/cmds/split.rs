@@ -0,0 +1,269 @@
+use std::os::raw::c_char;
+use std::path::PathBuf;
+use std::ptr::null_mut;
+
+use ffmpeg_dev::sys;
+
+use crate::av::input_ctx::InputCtx;
+use crate::av::output_ctx::OutputCtx;
+use crate::av::utils;
+use crate::opts;
+
+// one output file, all times in AV_TIME_BASE units relative to the input start
+struct Part {
+    path: String,
+    output: OutputCtx,
+    start: i64,
+    end: i64,
+}
+
+pub fn run(args: opts::Split) {
+    let input_path = args.input.as_str();
+
+    assert!(
+        PathBuf::from(input_path).exists(),
+        "file {} does not exist",
+        input_path
+    );
+
+    assert!(
+        args.segment_duration.is_some() || args.max_size.is_some(),
+        "either --segment-duration or --max-size is required"
+    );
+
+    unsafe {
+        let input = InputCtx::open(
+            input_path,
+            args.input_format.format.as_deref(),
+            &args.input_format.options(),
+        );
+        let in_streams = input.get_streams();
+
+        let input_start = if (*input.av).start_time == utils::AV_NOPTS_VALUE {
+            0
+        } else {
+            (*input.av).start_time
+        };
+
+        // parts are cut at keyframes of this stream (video, if any)
+        let reference = input
+            .find_stream(sys::AVMediaType_AVMEDIA_TYPE_VIDEO)
+            .unwrap_or(0) as usize;
+
+        let max_duration = args
+            .segment_duration
+            .map(|seconds| (seconds * sys::AV_TIME_BASE as f64) as i64);
+
+        let mut parts: Vec<(String, i64, i64)> = Vec::new();
+        let mut part: Option<Part> = None;
+
+        while sys::av_read_frame(input.av, input.packet) >= 0 {
+            let packet = input.packet;
+            let index = (*packet).stream_index as usize;
+            let time_base = (*in_streams[index]).time_base;
+
+            let ts = if (*packet).pts != utils::AV_NOPTS_VALUE {
+                (*packet).pts
+            } else {
+                (*packet).dts
+            };
+
+            let time = if ts == utils::AV_NOPTS_VALUE {
+                None
+            } else {
+                Some(sys::av_rescale_q(ts, time_base, utils::av_time_base_q()) - input_start)
+            };
+
+            let keyframe = index == reference && (*packet).flags & sys::AV_PKT_FLAG_KEY as i32 != 0;
+
+            let cut = match (&part, time) {
+                (None, _) => true,
+                (Some(current), Some(time)) if keyframe => {
+                    let too_long = max_duration.map_or(false, |max| time - current.start >= max);
+                    // only the size so far and the keyframe are known, so a part can still end
+                    // up over the limit by up to a GOP and the trailer, see `finish`
+                    let too_big = args.max_size.map_or(false, |max| {
+                        written(&current.output) + (*packet).size as u64 > max
+                    });
+
+                    too_long || too_big
+                }
+                _ => false,
+            };
+
+            if cut {
+                if let Some(previous) = part.take() {
+                    parts.push(finish(previous, args.max_size));
+                }
+
+                let start = time.unwrap_or(0);
+                part = Some(open_part(
+                    &args.output,
+                    &args.output_format,
+                    parts.len(),
+                    &input,
+                    start,
+                    args.max_size,
+                ));
+            }
+
+            let current = part.as_mut().unwrap();
+
+            if let Some(time) = time {
+                let duration =
+                    sys::av_rescale_q((*packet).duration, time_base, utils::av_time_base_q());
+                current.end = current.end.max(time + duration);
+            }
+
+            // every part starts at zero, packets of other streams slightly before the cut go
+            // negative and are handled by the muxer
+            let rebase = sys::av_rescale_q(
+                current.start + input_start,
+                utils::av_time_base_q(),
+                time_base,
+            );
+
+            if (*packet).pts != utils::AV_NOPTS_VALUE {
+                (*packet).pts -= rebase;
+            }
+
+            if (*packet).dts != utils::AV_NOPTS_VALUE {
+                (*packet).dts -= rebase;
+            }
+
+            current.output.write_packet(packet, time_base);
+        }
+
+        if let Some(last) = part.take() {
+            parts.push(finish(last, args.max_size));
+        }
+
+        let entries: Vec<String> = parts
+            .iter()
+            .map(|(path, start, duration)| {
+                format!(
+                    "    {{\"file\": \"{}\", \"start\": {:.3}, \"duration\": {:.3}}}",
+                    escape_json(path),
+                    *start as f64 / sys::AV_TIME_BASE as f64,
+                    *duration as f64 / sys::AV_TIME_BASE as f64
+                )
+            })
+            .collect();
+
+        let manifest = format!("{{\n  \"parts\": [\n{}\n  ]\n}}\n", entries.join(",\n"));
+
+        utils::write_report(&args.manifest, &manifest);
+    }
+}
+
+// fills in the part number of a printf-style pattern, e.g. part%03d.mp4
+unsafe fn part_path(pattern: &str, number: usize) -> String {
+    let pattern_str = utils::str_to_c_str(pattern);
+    let mut buf = vec![0 as c_char; 1024];
+
+    let response = sys::av_get_frame_filename(
+        buf.as_mut_ptr(),
+        buf.len() as i32,
+        pattern_str.as_ptr(),
+        number as i32,
+    );
+
+    assert!(
+        response >= 0,
+        "output pattern {} needs a number placeholder, e.g. part%03d.mp4",
+        pattern
+    );
+
+    utils::c_str_to_string(buf.as_ptr())
+}
+
+unsafe fn open_part(
+    pattern: &str,
+    output_format: &opts::OutputFormat,
+    number: usize,
+    input: &InputCtx,
+    start: i64,
+    max_size: Option<u64>,
+) -> Part {
+    let path = part_path(pattern, number);
+    let mut output = OutputCtx::open(&path, output_format.format.as_deref());
+
+    // the size of a part is only known when the muxer writes it through our own file
+    assert!(
+        max_size.is_none() || (*(*output.av).oformat).flags & sys::AVFMT_NOFILE as i32 == 0,
+        "--max-size can't be used with the {} format, which writes its own files",
+        utils::c_str_to_string((*(*output.av).oformat).name)
+    );
+
+    for in_stream in input.get_streams().iter() {
+        let out_stream = sys::avformat_new_stream(output.av, null_mut());
+        assert!(out_stream != null_mut(), "failed to allocate output stream");
+
+        let response = sys::avcodec_parameters_copy((*out_stream).codecpar, (**in_stream).codecpar);
+        utils::check_error(response);
+
+        (*(*out_stream).codecpar).codec_tag = 0;
+    }
+
+    output.open_file_with_options(&path, &output_format.options());
+
+    println!(
+        "Writing {} from {}",
+        path,
+        utils::format_timestamp(start as f64 / sys::AV_TIME_BASE as f64)
+    );
+
+    Part {
+        path,
+        output,
+        start,
+        end: start,
+    }
+}
+
+// bytes written to a part so far
+unsafe fn written(output: &OutputCtx) -> u64 {
+    let pb = (*output.av).pb;
+    assert!(pb != null_mut(), "output has no file to measure");
+
+    sys::avio_tell(pb) as u64
+}
+
+// closes the part, returns its manifest entry
+unsafe fn finish(part: Part, max_size: Option<u64>) -> (String, i64, i64) {
+    sys::av_write_trailer(part.output.av);
+
+    if let Some(max) = max_size {
+        let size = written(&part.output);
+
+        if size > max {
+            println!(
+                "warning: {} is {} bytes, {} over --max-size",
+                part.path,
+                size,
+                size - max
+            );
+        }
+    }
+
+    (part.path, part.start, part.end - part.start)
+}
+
+// escapes a string for use inside a json string literal
+fn escape_json(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_manifest_paths() {
+        assert_eq!(escape_json("parts/a.mp4"), "parts/a.mp4");
+        assert_eq!(
+            escape_json("C:\\parts\\\"quoted\".mp4"),
+            "C:\\\\parts\\\\\\\"quoted\\\".mp4"
+        );
+    }
+}
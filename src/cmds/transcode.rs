@@ -1,12 +1,14 @@
 use std::path::PathBuf;
 use std::ptr::null_mut;
-use std::slice;
 
-use ffmpeg_dev::extra::defs::{averror, averror_eof, eagain};
 use ffmpeg_dev::sys;
 
+use crate::av::encoder::Encoder;
 use crate::av::input_ctx::InputCtx;
 use crate::av::output_ctx::OutputCtx;
+use crate::av::progress::Progress;
+use crate::av::stream_map;
+use crate::av::transcoder::StreamTranscoder;
 use crate::av::utils;
 use crate::opts;
 
@@ -23,111 +25,79 @@ pub fn run(args: opts::Transcode) {
     unsafe {
         sys::av_register_all();
 
-        let input = InputCtx::new(input_path);
-        let mut output = OutputCtx::new(output_path);
+        let input = InputCtx::open(
+            input_path,
+            args.input_format.format.as_deref(),
+            &args.input_format.options(),
+        );
+        let mut output = OutputCtx::open(output_path, args.output_format.format.as_deref());
 
         let in_streams = input.get_streams();
         let mut out_streams = Vec::new();
-        let video_stream_index = 0;
-        let mut decoder_codec: *mut sys::AVCodec;
-        let mut decoder_codec_ctx: *mut sys::AVCodecContext = null_mut();
-        let mut encoder_codec: *mut sys::AVCodec;
-        let mut encoder_codec_ctx: *mut sys::AVCodecContext = null_mut();
+        // streams being re-encoded, by stream index
+        let mut transcoders: Vec<Option<StreamTranscoder>> = Vec::new();
 
         for i in 0..in_streams.len() {
             let in_stream = in_streams[i];
             let out_stream = sys::avformat_new_stream(output.av, null_mut());
+            let mut transcoder = None;
 
-            // if this is the video stream
-            if (*(*in_stream).codecpar).codec_type == sys::AVMediaType_AVMEDIA_TYPE_VIDEO {
-                // open decoder codec
-                decoder_codec = sys::avcodec_find_decoder((*(*in_stream).codecpar).codec_id);
-                decoder_codec_ctx = sys::avcodec_alloc_context3(decoder_codec);
-                sys::avcodec_parameters_to_context(decoder_codec_ctx, (*in_stream).codecpar);
-                sys::avcodec_open2(decoder_codec_ctx, decoder_codec, null_mut());
-
-                // open h265 codec
-                let encoder_codec_name = utils::str_to_c_str("libx265");
-                let encoder_codec_priv_key = utils::str_to_c_str("x265-params");
-                let encoder_codec_priv_value =
-                    utils::str_to_c_str("keyint=60:min-keyint=60:scenecut=0");
-
-                encoder_codec = sys::avcodec_find_encoder_by_name(encoder_codec_name.as_ptr());
-                encoder_codec_ctx = sys::avcodec_alloc_context3(encoder_codec);
-
-                sys::av_opt_set(
-                    (*encoder_codec_ctx).priv_data,
-                    encoder_codec_priv_key.as_ptr(),
-                    encoder_codec_priv_value.as_ptr(),
-                    0,
-                );
+            let codecpar = (*in_stream).codecpar;
+            let attached_pic = (*in_stream).disposition & sys::AV_DISPOSITION_ATTACHED_PIC as i32;
+
+            // video streams (but not cover art) are re-encoded with the chosen encoder
+            if (*codecpar).codec_type == sys::AVMediaType_AVMEDIA_TYPE_VIDEO && attached_pic == 0 {
+                let mut encoder = Encoder::new(&args.vcodec);
+                configure_encoder(&mut encoder, &args);
 
-                // encoder codec params
-                (*encoder_codec_ctx).height = (*decoder_codec_ctx).height;
-                (*encoder_codec_ctx).width = (*decoder_codec_ctx).width;
-                let pix_fmts_array = slice::from_raw_parts((*encoder_codec).pix_fmts, 1);
-                (*encoder_codec_ctx).pix_fmt = pix_fmts_array[0];
+                transcoder = Some(StreamTranscoder::with_encoder(
+                    &input, in_stream, encoder, &output, out_stream,
+                ));
 
-                // control rate
-                (*encoder_codec_ctx).bit_rate = 2 * 1000 * 1000;
-                (*encoder_codec_ctx).rc_buffer_size = 4 * 1000 * 1000;
-                (*encoder_codec_ctx).rc_max_rate = 2 * 1000 * 1000;
-                (*encoder_codec_ctx).rc_min_rate = (2.5f64 * 1000f64 * 1000f64) as i64;
+            // audio gets decoded, resampled and re-encoded when needed
+            } else if transcode_audio(&output, in_stream, &args) {
+                let encoder = audio_encoder(&output, &args);
 
-                // time base
-                let input_framerate = sys::av_guess_frame_rate(input.av, in_stream, null_mut());
-                let time_base = utils::av_inv_q(input_framerate);
-                (*encoder_codec_ctx).time_base = time_base;
-                (*out_stream).time_base = time_base;
+                println!(
+                    "Stream #{}: re-encoding audio with {}",
+                    i,
+                    utils::c_str_to_string((*encoder.codec).name)
+                );
 
-                sys::avcodec_open2(encoder_codec_ctx, encoder_codec, null_mut());
-                sys::avcodec_parameters_from_context((*out_stream).codecpar, encoder_codec_ctx);
+                transcoder = Some(StreamTranscoder::with_encoder(
+                    &input, in_stream, encoder, &output, out_stream,
+                ));
 
             // and for all other streams
             // just copy codec params
             } else {
                 sys::avcodec_parameters_copy((*out_stream).codecpar, (*in_stream).codecpar);
-
-                if (*(*output.av).oformat).flags & sys::AVFMT_GLOBALHEADER as i32 > 0 {
-                    (*output.av).flags |= sys::AV_CODEC_FLAG_GLOBAL_HEADER as i32;
-                }
             }
 
+            stream_map::tag_output_stream(out_stream, i, &args.dispositions, &args.languages);
+
             out_streams.push(out_stream);
+            transcoders.push(transcoder);
         }
 
-        output.open_file(output_path);
+        stream_map::check_output_tags(out_streams.len(), &args.dispositions, &args.languages);
+
+        output.set_mp4_mode(args.mp4_mode.as_ref(), args.frag_duration);
+        output.open_file_with_options(output_path, &args.output_format.options());
+
+        let mut progress = Progress::new(&args.progress, &input, 0, None);
 
         while sys::av_read_frame(input.av, input.packet) >= 0 {
+            progress.update(&input, &output);
+
             let index = (*input.packet).stream_index as usize;
 
             let in_stream = in_streams[index];
             let out_stream = out_streams[index];
 
-            // if this is a video packet
-            if index == video_stream_index {
-                let response = sys::avcodec_send_packet(decoder_codec_ctx, input.packet);
-
-                while response >= 0 {
-                    let response = sys::avcodec_receive_frame(decoder_codec_ctx, input.frame);
-
-                    if response == averror(eagain()) || response == averror_eof() {
-                        break;
-                    } else if response < 0 {
-                        return;
-                    }
-
-                    if response >= 0 {
-                        encode(
-                            output.av,
-                            input.frame,
-                            in_stream,
-                            out_stream,
-                            encoder_codec_ctx,
-                            (*input.packet).stream_index,
-                        );
-                    }
-                }
+            if let Some(transcoder) = transcoders[index].as_mut() {
+                transcoder.send_packet(input.packet, &mut output, index);
+                sys::av_packet_unref(input.packet);
             } else {
                 sys::av_packet_rescale_ts(
                     input.packet,
@@ -138,44 +108,136 @@ pub fn run(args: opts::Transcode) {
             }
         }
 
+        // drain every decoder and encoder, video encoders hold frames back for lookahead and
+        // b-frames, audio ones the samples short of a whole frame
+        for (index, transcoder) in transcoders.iter_mut().enumerate() {
+            if let Some(transcoder) = transcoder {
+                transcoder.flush(&mut output, index);
+            }
+        }
+
         sys::av_write_trailer(output.av);
+        progress.finish(&output);
     }
 }
 
-unsafe fn encode(
-    av_ctx: *mut sys::AVFormatContext,
-    frame: *mut sys::AVFrame,
-    in_stream: *mut sys::AVStream,
-    out_stream: *mut sys::AVStream,
-    codec_ctx: *mut sys::AVCodecContext,
-    index: i32,
-) -> i32 {
-    let mut packet = sys::av_packet_alloc();
-    let mut response = sys::avcodec_send_frame(codec_ctx, frame);
-
-    while response >= 0 {
-        response = sys::avcodec_receive_packet(codec_ctx, packet);
-
-        if response == averror(eagain()) || response == averror_eof() {
-            break;
-        } else if response < 0 {
-            return -1;
+// rate control and gop options, validated against the encoder's AVOptions
+unsafe fn configure_encoder(encoder: &mut Encoder, args: &opts::Transcode) {
+    let mut options = Vec::new();
+
+    // libx265 keeps the settings this command has always used, unless told otherwise
+    if args.vcodec == "libx265" {
+        let rate_control = args.crf.is_some()
+            || args.bitrate.is_some()
+            || args.maxrate.is_some()
+            || args.bufsize.is_some();
+
+        if !rate_control {
+            options.push(("b".to_string(), "2000000".to_string()));
+            options.push(("maxrate".to_string(), "2000000".to_string()));
+            options.push(("bufsize".to_string(), "4000000".to_string()));
+        }
+
+        let x265_params = args.encoder_opts.iter().any(|opt| opt.key == "x265-params");
+
+        if args.keyint.is_none() && !x265_params {
+            options.push((
+                "x265-params".to_string(),
+                "keyint=60:min-keyint=60:scenecut=0".to_string(),
+            ));
         }
+    }
+
+    if let Some(preset) = &args.preset {
+        options.push(("preset".to_string(), preset.clone()));
+    }
+
+    if let Some(crf) = args.crf {
+        options.push(("crf".to_string(), crf.to_string()));
+    }
+
+    // rates accept suffixes, e.g. 2M or 500k
+    if let Some(bitrate) = &args.bitrate {
+        options.push(("b".to_string(), bitrate.clone()));
+    }
 
-        (*packet).stream_index = index;
+    if let Some(maxrate) = &args.maxrate {
+        options.push(("maxrate".to_string(), maxrate.clone()));
+    }
+
+    if let Some(bufsize) = &args.bufsize {
+        options.push(("bufsize".to_string(), bufsize.clone()));
+    }
+
+    if let Some(keyint) = args.keyint {
+        options.push(("g".to_string(), keyint.to_string()));
+    }
+
+    // raw encoder options come last, so they can override any of the above
+    options.extend(opts::key_values(&args.encoder_opts));
+
+    for (key, value) in options.iter() {
+        encoder.set_option(key, value);
+    }
+
+    if args.maxrate.is_some() && args.bufsize.is_none() {
+        println!("warning: --maxrate usually needs --bufsize to take effect");
+    }
+}
 
-        let out_time = (*out_stream).time_base;
-        let frame_rate = (*in_stream).avg_frame_rate;
+// audio is re-encoded when any audio option is given, or when the container can't hold the
+// source codec
+unsafe fn transcode_audio(
+    output: &OutputCtx,
+    in_stream: *mut sys::AVStream,
+    args: &opts::Transcode,
+) -> bool {
+    let codecpar = (*in_stream).codecpar;
 
-        (*packet).duration =
-            (out_time.den as i64) / (out_time.num as i64) / (frame_rate.num as i64)
-                * (frame_rate.den as i64);
-        sys::av_packet_rescale_ts(packet, (*in_stream).time_base, (*out_stream).time_base);
-        response = sys::av_interleaved_write_frame(av_ctx, packet);
+    if (*codecpar).codec_type != sys::AVMediaType_AVMEDIA_TYPE_AUDIO {
+        return false;
     }
 
-    sys::av_packet_unref(packet);
-    sys::av_packet_free(&mut packet);
+    let requested =
+        args.acodec.is_some() || args.ab.is_some() || args.ar.is_some() || args.ac.is_some();
+
+    requested
+        || sys::avformat_query_codec(
+            (*output.av).oformat,
+            (*codecpar).codec_id,
+            sys::FF_COMPLIANCE_NORMAL as i32,
+        ) == 0
+}
+
+// --acodec or the container's default audio encoder, with the requested bitrate, sample rate
+// and channel count (anything left unset follows the source)
+unsafe fn audio_encoder(output: &OutputCtx, args: &opts::Transcode) -> Encoder {
+    let mut encoder = match &args.acodec {
+        Some(name) => Encoder::new(name),
+        None => {
+            let codec_id = (*(*output.av).oformat).audio_codec;
+            assert!(
+                codec_id != sys::AVCodecID_AV_CODEC_ID_NONE,
+                "output format has no default audio codec, use --acodec"
+            );
+
+            Encoder::for_codec(codec_id)
+        }
+    };
+
+    if let Some(bitrate) = &args.ab {
+        encoder.set_option("b", bitrate);
+    }
+
+    if let Some(sample_rate) = args.ar {
+        assert!(sample_rate > 0, "invalid sample rate {}", sample_rate);
+        (*encoder.codec_ctx).sample_rate = sample_rate;
+    }
+
+    if let Some(channels) = args.ac {
+        assert!(channels > 0, "invalid channel count {}", channels);
+        (*encoder.codec_ctx).channels = channels;
+    }
 
-    return 0;
+    encoder
 }
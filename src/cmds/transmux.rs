@@ -3,10 +3,29 @@ use std::ptr::null_mut;
 
 use ffmpeg_dev::sys;
 
+use crate::av::bsf::{self, Bsf, BsfSpec};
 use crate::av::input_ctx::InputCtx;
 use crate::av::output_ctx::OutputCtx;
+use crate::av::preflight::Preflight;
+use crate::av::progress::Progress;
+use crate::av::stream_map::StreamMap;
+use crate::av::timestamps::TimestampFixer;
+use crate::av::transcoder::StreamTranscoder;
 use crate::opts;
 
+// what happens to the mapped packets on their way to the output, per output stream
+#[derive(Default)]
+pub struct Pipeline {
+    // bitstream filter of each stream-copied output stream
+    pub filters: Vec<Option<Bsf>>,
+    // streams with a transcoder are re-encoded instead of stream-copied
+    pub transcoders: Vec<Option<StreamTranscoder>>,
+    // repairs timestamps before anything else
+    pub fixer: Option<TimestampFixer>,
+    // reported as packets are read
+    pub progress: Option<Progress>,
+}
+
 pub fn run(args: opts::Transmux) {
     let input_path = args.input.as_str();
     let output_path = args.output.as_str();
@@ -18,54 +37,219 @@ pub fn run(args: opts::Transmux) {
     );
 
     unsafe {
-        let input = InputCtx::new(input_path);
-        let mut output = OutputCtx::new(output_path);
+        let input = InputCtx::open(
+            input_path,
+            args.input_format.format.as_deref(),
+            &args.input_format.options(),
+        );
+        let mut output = OutputCtx::open(output_path, args.output_format.format.as_deref());
 
-        let in_streams = input.get_streams();
-        let mut out_streams = Vec::new();
+        let mut map = StreamMap::new(&input, &args.map, args.drop_data);
+        map.print(&input);
 
-        for i in 0..in_streams.len() {
-            let in_stream = in_streams[i];
-            let codec = sys::avcodec_find_decoder((*(*in_stream).codecpar).codec_id);
-            let codec_ctx = sys::avcodec_alloc_context3(codec);
+        let preflight = Preflight::new(&input, &output, &map);
+        preflight.print(&input, &output);
+        preflight.check(&mut map, args.auto);
 
-            // open codec
-            sys::avcodec_parameters_to_context(codec_ctx, (*in_stream).codecpar);
-            sys::avcodec_open2(codec_ctx, codec, null_mut());
+        let out_streams = add_streams(&input, &mut output, &map);
 
-            // create output stream
-            let out_stream = sys::avformat_new_stream(output.av, null_mut());
-            sys::avcodec_parameters_copy((*out_stream).codecpar, (*in_stream).codecpar);
+        let mut pipeline = Pipeline {
+            transcoders: add_transcoders(&input, &output, &map, &out_streams, &preflight),
+            filters: add_filters(&input, &output, &map, &out_streams, &args.bsf),
+            fixer: if args.fix_timestamps {
+                Some(TimestampFixer::new(&input))
+            } else {
+                None
+            },
+            progress: None,
+        };
 
-            if (*(*output.av).oformat).flags & sys::AVFMT_GLOBALHEADER as i32 > 0 {
-                (*output.av).flags |= sys::AV_CODEC_FLAG_GLOBAL_HEADER as i32;
-            }
+        output.open_file_with_options(output_path, &args.output_format.options());
+
+        pipeline.progress = Some(Progress::new(&args.progress, &input, 0, None));
+
+        copy_packets(&input, &mut output, &map, &mut pipeline);
+
+        sys::av_write_trailer(output.av);
+
+        if let Some(progress) = pipeline.progress.as_mut() {
+            progress.finish(&output);
+        }
+
+        if let Some(fixer) = &pipeline.fixer {
+            fixer.report(&input);
+        }
+    }
+}
 
-            out_streams.push(out_stream);
+// creates one output stream per mapped input stream, with the same codec parameters
+pub unsafe fn add_streams(
+    input: &InputCtx,
+    output: &mut OutputCtx,
+    map: &StreamMap,
+) -> Vec<*mut sys::AVStream> {
+    let in_streams = input.get_streams();
+    let mut out_streams = Vec::new();
+
+    for &i in map.inputs.iter() {
+        let in_stream = in_streams[i];
+
+        // create output stream
+        let out_stream = sys::avformat_new_stream(output.av, null_mut());
+        sys::avcodec_parameters_copy((*out_stream).codecpar, (*in_stream).codecpar);
+
+        // codec tags are container specific, let the muxer pick its own
+        (*(*out_stream).codecpar).codec_tag = 0;
+
+        out_streams.push(out_stream);
+    }
+
+    out_streams
+}
+
+// sets up re-encoding for the streams the preflight found unfit for the container
+pub unsafe fn add_transcoders(
+    input: &InputCtx,
+    output: &OutputCtx,
+    map: &StreamMap,
+    out_streams: &[*mut sys::AVStream],
+    preflight: &Preflight,
+) -> Vec<Option<StreamTranscoder>> {
+    map.inputs
+        .iter()
+        .zip(out_streams.iter())
+        .map(|(&i, &out_stream)| {
+            preflight.reencode(i).map(|codec_id| {
+                StreamTranscoder::new(input, input.get_stream(i), codec_id, output, out_stream)
+            })
+        })
+        .collect()
+}
+
+// picks a bitstream filter for each output stream, either from `overrides` or based on the
+// source and target containers, and updates the output codec parameters to the filtered ones
+pub unsafe fn add_filters(
+    input: &InputCtx,
+    output: &OutputCtx,
+    map: &StreamMap,
+    out_streams: &[*mut sys::AVStream],
+    overrides: &[BsfSpec],
+) -> Vec<Option<Bsf>> {
+    let in_streams = input.get_streams();
+    let mut filters = Vec::new();
+
+    for (out_index, &i) in map.inputs.iter().enumerate() {
+        let in_stream = in_streams[i];
+        let out_stream = out_streams[out_index];
+
+        // re-encoded streams get fresh codec parameters, there is nothing to convert
+        if (*(*out_stream).codecpar).codec_id != (*(*in_stream).codecpar).codec_id {
+            filters.push(None);
+            continue;
         }
 
-        output.open_file(output_path);
+        let filter = match overrides.iter().rev().find(|spec| spec.stream == i) {
+            Some(spec) if spec.filter == "none" => None,
+            Some(spec) => Some(spec.filter.clone()),
+            None => bsf::auto_filter((*in_stream).codecpar, output).map(|name| name.to_string()),
+        };
+
+        let filter = filter.map(|name| {
+            println!("Stream #{}: applying bitstream filter {}", i, name);
+
+            let filter = Bsf::new(&name, in_stream);
+
+            sys::avcodec_parameters_copy((*out_stream).codecpar, filter.output_params());
+            (*(*out_stream).codecpar).codec_tag = 0;
 
-        loop {
-            let ret = sys::av_read_frame(input.av, input.packet);
+            filter
+        });
 
-            if ret < 0 {
-                break;
+        filters.push(filter);
+    }
+
+    filters
+}
+
+// stream-copies every mapped packet until the end of the input, through the pipeline
+pub unsafe fn copy_packets(
+    input: &InputCtx,
+    output: &mut OutputCtx,
+    map: &StreamMap,
+    pipeline: &mut Pipeline,
+) {
+    let in_streams = input.get_streams();
+
+    loop {
+        let ret = sys::av_read_frame(input.av, input.packet);
+
+        if ret < 0 {
+            break;
+        }
+
+        if let Some(progress) = pipeline.progress.as_mut() {
+            progress.update(input, output);
+        }
+
+        let index = (*input.packet).stream_index as usize;
+
+        let out_index = match map.output_index(index) {
+            Some(out_index) => out_index,
+            None => {
+                sys::av_packet_unref(input.packet);
+                continue;
             }
+        };
 
-            let index = (*input.packet).stream_index as usize;
+        if let Some(fixer) = pipeline.fixer.as_mut() {
+            fixer.fix(input.packet);
+        }
 
-            let in_stream = in_streams[index];
-            let out_stream = out_streams[index];
+        let transcoder = pipeline
+            .transcoders
+            .get_mut(out_index)
+            .and_then(Option::as_mut);
 
-            sys::av_packet_rescale_ts(
-                input.packet,
-                (*in_stream).time_base,
-                (*out_stream).time_base,
-            );
-            sys::av_interleaved_write_frame(output.av, input.packet);
+        if let Some(transcoder) = transcoder {
+            transcoder.send_packet(input.packet, output, out_index);
+            sys::av_packet_unref(input.packet);
+            continue;
         }
 
-        sys::av_write_trailer(output.av);
+        (*input.packet).stream_index = out_index as i32;
+
+        match pipeline.filters.get_mut(out_index).and_then(Option::as_mut) {
+            Some(filter) => {
+                filter.send_packet(input.packet);
+                write_filtered(output, filter, input.packet, out_index);
+            }
+            None => output.write_packet(input.packet, (*in_streams[index]).time_base),
+        }
+    }
+
+    // flush the filters and encoders
+    for (out_index, filter) in pipeline.filters.iter_mut().enumerate() {
+        if let Some(filter) = filter {
+            filter.send_packet(null_mut());
+            write_filtered(output, filter, input.packet, out_index);
+        }
+    }
+
+    for (out_index, transcoder) in pipeline.transcoders.iter_mut().enumerate() {
+        if let Some(transcoder) = transcoder {
+            transcoder.flush(output, out_index);
+        }
+    }
+}
+
+unsafe fn write_filtered(
+    output: &mut OutputCtx,
+    filter: &mut Bsf,
+    packet: *mut sys::AVPacket,
+    out_index: usize,
+) {
+    while filter.receive_packet(packet) {
+        (*packet).stream_index = out_index as i32;
+        output.write_packet(packet, filter.time_base());
     }
 }
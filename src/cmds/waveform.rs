@@ -0,0 +1,332 @@
+use std::path::PathBuf;
+use std::ptr::null_mut;
+
+use ffmpeg_dev::sys;
+
+use crate::av::decoder::Decoder;
+use crate::av::image::Image;
+use crate::av::input_ctx::InputCtx;
+use crate::av::resampler::Resampler;
+use crate::av::utils;
+use crate::opts;
+
+const BACKGROUND: [u8; 3] = [20, 20, 28];
+const PEAK_COLOR: [u8; 3] = [64, 140, 220];
+const RMS_COLOR: [u8; 3] = [150, 200, 255];
+
+// dB range mapped to the spectrogram colour ramp
+const MIN_DB: f32 = -100.0;
+
+// min/max/rms of the samples covered by one image column
+struct Column {
+    min: f32,
+    max: f32,
+    rms: f32,
+}
+
+struct Spectrogram {
+    fft_size: usize,
+    rdft: *mut sys::RDFTContext,
+    window: Vec<f32>,
+    // one magnitude spectrum (in dB) per column
+    spectra: Vec<Vec<f32>>,
+}
+
+impl Spectrogram {
+    unsafe fn new(fft_size: usize) -> Spectrogram {
+        assert!(
+            fft_size.is_power_of_two() && fft_size >= 16,
+            "fft size must be a power of two"
+        );
+
+        let bits = fft_size.trailing_zeros() as i32;
+        let rdft = sys::av_rdft_init(bits, sys::RDFTransformType_DFT_R2C);
+
+        // hann window
+        let window = (0..fft_size)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * i as f32 / (fft_size - 1) as f32;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+
+        Spectrogram {
+            fft_size,
+            rdft,
+            window,
+            spectra: Vec::new(),
+        }
+    }
+
+    // analyses the first `fft_size` samples of a column, zero-padding if needed
+    unsafe fn push(&mut self, samples: &[f32]) {
+        let mut data: Vec<f32> = (0..self.fft_size)
+            .map(|i| samples.get(i).unwrap_or(&0.0) * self.window[i])
+            .collect();
+
+        sys::av_rdft_calc(self.rdft, data.as_mut_ptr());
+
+        // output is packed as [dc, nyquist, re1, im1, re2, im2, ...]
+        let bins = self.fft_size / 2;
+        let mut spectrum = Vec::with_capacity(bins);
+        spectrum.push(data[0].abs());
+
+        for bin in 1..bins {
+            let re = data[2 * bin];
+            let im = data[2 * bin + 1];
+            spectrum.push((re * re + im * im).sqrt());
+        }
+
+        let spectrum = spectrum
+            .iter()
+            .map(|magnitude| 20.0 * (magnitude * 2.0 / bins as f32 + 1e-10).log10())
+            .collect();
+
+        self.spectra.push(spectrum);
+    }
+
+    fn render(&self, height: i32) -> Image {
+        let mut image = Image::new(self.spectra.len() as i32, height, [0, 0, 0]);
+        let bins = self.fft_size / 2;
+
+        for (x, spectrum) in self.spectra.iter().enumerate() {
+            for y in 0..height {
+                // low frequencies at the bottom
+                let bin = (height - 1 - y) as usize * bins / height as usize;
+                let level = ((spectrum[bin] - MIN_DB) / -MIN_DB).max(0.0).min(1.0);
+
+                image.put_pixel(x as i32, y, heat_color(level));
+            }
+        }
+
+        image
+    }
+}
+
+impl Drop for Spectrogram {
+    fn drop(&mut self) {
+        unsafe { sys::av_rdft_end(self.rdft) }
+    }
+}
+
+pub fn run(args: opts::Waveform) {
+    let path = args.input.as_str();
+
+    assert!(PathBuf::from(path).exists(), "file {} does not exist", path);
+
+    unsafe {
+        let input = InputCtx::new(path);
+
+        let index = match args.stream {
+            Some(index) => index,
+            None => input
+                .find_stream(sys::AVMediaType_AVMEDIA_TYPE_AUDIO)
+                .expect("no audio stream found"),
+        };
+
+        let in_stream = input.get_stream(index as usize);
+        let mut decoder = Decoder::new(in_stream);
+        let sample_rate = (*decoder.codec_ctx).sample_rate;
+
+        // downmix everything to mono float samples
+        let mut resampler = Resampler::from_decoder(
+            decoder.codec_ctx,
+            sys::AV_CH_LAYOUT_MONO as i64,
+            sys::AVSampleFormat_AV_SAMPLE_FMT_FLT,
+            sample_rate,
+        );
+
+        // spread the whole duration across the image width
+        let (_, duration) = input.stream_range(index as usize);
+        let total_samples = sys::av_rescale_q(
+            duration,
+            (*in_stream).time_base,
+            sys::AVRational {
+                num: 1,
+                den: sample_rate,
+            },
+        );
+        let samples_per_column = ((total_samples / args.width as i64) + 1) as usize;
+
+        let mut spectrogram = match &args.spectrogram {
+            Some(_) => Some(Spectrogram::new(args.fft_size)),
+            None => None,
+        };
+
+        let mut columns = Vec::with_capacity(args.width as usize);
+        let mut pending: Vec<f32> = Vec::with_capacity(samples_per_column);
+        let mut samples: Vec<f32> = Vec::new();
+
+        loop {
+            let more = sys::av_read_frame(input.av, input.packet) >= 0;
+
+            if more {
+                if (*input.packet).stream_index == index {
+                    decoder.send_packet(input.packet);
+                }
+
+                sys::av_packet_unref(input.packet);
+            } else {
+                decoder.send_packet(null_mut());
+            }
+
+            // once the decoder is drained, a null frame flushes the resampler's buffered tail
+            loop {
+                let frame = if decoder.receive_frame(input.frame) {
+                    input.frame
+                } else if !more {
+                    null_mut()
+                } else {
+                    break;
+                };
+
+                let in_samples = if frame.is_null() {
+                    0
+                } else {
+                    (*frame).nb_samples
+                };
+
+                samples.resize(resampler.out_samples(in_samples) as usize, 0.0);
+
+                let mut out = samples.as_mut_ptr() as *mut u8;
+                let count = resampler.convert(frame, &mut out, samples.len() as i32);
+
+                for &sample in &samples[..count as usize] {
+                    pending.push(sample);
+
+                    if pending.len() == samples_per_column {
+                        columns.push(column(&pending));
+
+                        if let Some(spectrogram) = spectrogram.as_mut() {
+                            spectrogram.push(&pending);
+                        }
+
+                        pending.clear();
+                    }
+                }
+
+                if frame.is_null() {
+                    break;
+                }
+            }
+
+            if !more {
+                break;
+            }
+        }
+
+        if !pending.is_empty() {
+            columns.push(column(&pending));
+
+            if let Some(spectrogram) = spectrogram.as_mut() {
+                spectrogram.push(&pending);
+            }
+        }
+
+        println!(
+            "Rendering {} columns of {} samples into {}",
+            columns.len(),
+            samples_per_column,
+            args.output
+        );
+
+        render_waveform(&columns, args.height).save(args.output.as_str());
+
+        if let (Some(spectrogram), Some(path)) = (&spectrogram, &args.spectrogram) {
+            println!("Rendering spectrogram into {}", path);
+            spectrogram.render(args.height).save(path.as_str());
+        }
+
+        if let Some(path) = &args.peaks {
+            println!("Writing peaks into {}", path);
+            utils::write_report(
+                &Some(path.clone()),
+                &peaks_json(&columns, sample_rate, samples_per_column),
+            );
+        }
+    }
+}
+
+fn column(samples: &[f32]) -> Column {
+    let mut min = 0.0f32;
+    let mut max = 0.0f32;
+    let mut sum = 0.0f32;
+
+    for &sample in samples {
+        min = min.min(sample);
+        max = max.max(sample);
+        sum += sample * sample;
+    }
+
+    Column {
+        min,
+        max,
+        rms: (sum / samples.len() as f32).sqrt(),
+    }
+}
+
+fn render_waveform(columns: &[Column], height: i32) -> Image {
+    let mut image = Image::new(columns.len() as i32, height, BACKGROUND);
+    let center = height as f32 / 2.0;
+
+    let to_y = |value: f32| (center - value.max(-1.0).min(1.0) * center) as i32;
+
+    for (x, column) in columns.iter().enumerate() {
+        let x = x as i32;
+
+        let top = to_y(column.max);
+        let bottom = to_y(column.min);
+        image.fill_rect(x, top, 1, (bottom - top).max(1), PEAK_COLOR);
+
+        let top = to_y(column.rms);
+        let bottom = to_y(-column.rms);
+        image.fill_rect(x, top, 1, (bottom - top).max(1), RMS_COLOR);
+    }
+
+    image
+}
+
+// black -> blue -> red -> yellow -> white
+fn heat_color(level: f32) -> [u8; 3] {
+    let stops: [[f32; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 160.0],
+        [200.0, 0.0, 60.0],
+        [255.0, 200.0, 0.0],
+        [255.0, 255.0, 255.0],
+    ];
+
+    let position = level * (stops.len() - 1) as f32;
+    let i = (position.floor() as usize).min(stops.len() - 2);
+    let t = position - i as f32;
+
+    let mut color = [0u8; 3];
+
+    for c in 0..3 {
+        color[c] = (stops[i][c] + (stops[i + 1][c] - stops[i][c]) * t) as u8;
+    }
+
+    color
+}
+
+// same layout as the audiowaveform JSON format, understood by peaks.js and similar players
+fn peaks_json(columns: &[Column], sample_rate: i32, samples_per_column: usize) -> String {
+    let data: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            format!(
+                "{},{}",
+                (column.min * i16::max_value() as f32) as i16,
+                (column.max * i16::max_value() as f32) as i16
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"version\":2,\"channels\":1,\"sample_rate\":{},\"samples_per_pixel\":{},\"bits\":16,\"length\":{},\"data\":[{}]}}\n",
+        sample_rate,
+        samples_per_column,
+        columns.len(),
+        data.join(",")
+    )
+}
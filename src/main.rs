@@ -15,6 +15,19 @@ fn main() {
         Remux(args) => remux::run(args),
         Transmux(args) => transmux::run(args),
         Transcode(args) => transcode::run(args),
+        ContactSheet(args) => contact_sheet::run(args),
+        Scenes(args) => scenes::run(args),
+        FrameHash(args) => framehash::run(args),
+        ImagesToVideo(args) => images_to_video::run(args),
+        Qc(args) => qc::run(args),
+        Waveform(args) => waveform::run(args),
+        Cut(args) => cut::run(args),
+        Concat(args) => concat::run(args),
+        Hls(args) => hls::run(args),
+        Dash(args) => dash::run(args),
+        Split(args) => split::run(args),
+        Mux(args) => mux::run(args),
+        Extract(args) => extract::run(args),
         Formats => formats::run(),
     }
 }
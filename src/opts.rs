@@ -1,7 +1,15 @@
 extern crate clap;
 
+use std::str::FromStr;
+
 use clap::Clap;
 
+use crate::av::bsf::BsfSpec;
+use crate::av::mp4::Mp4Mode;
+use crate::av::progress::ProgressMode;
+use crate::av::stream_map::{DispositionSpec, InputMapSpec, LanguageSpec, MapSpec};
+use crate::av::utils;
+
 #[derive(Clap)]
 #[clap(version = "1.0", author = "Miguel Palhas <mpalhas@gmail.com")]
 pub struct Opts {
@@ -15,6 +23,20 @@ pub enum SubCommand {
     Remux(Remux),
     Transmux(Transmux),
     Transcode(Transcode),
+    ContactSheet(ContactSheet),
+    Scenes(Scenes),
+    #[clap(name = "framehash")]
+    FrameHash(FrameHash),
+    ImagesToVideo(ImagesToVideo),
+    Qc(Qc),
+    Waveform(Waveform),
+    Cut(Cut),
+    Concat(Concat),
+    Hls(Hls),
+    Dash(Dash),
+    Split(Split),
+    Mux(Mux),
+    Extract(Extract),
     Formats,
 }
 
@@ -32,6 +54,41 @@ pub struct Remux {
     pub input: String,
     #[clap(short = "o", long = "output")]
     pub output: String,
+    #[clap(
+        short = "m",
+        long = "map",
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    pub map: Vec<MapSpec>,
+    #[clap(long = "drop-data")]
+    pub drop_data: bool,
+    #[clap(long = "auto")]
+    pub auto: bool,
+    #[clap(long = "fix-timestamps")]
+    pub fix_timestamps: bool,
+    #[clap(short = "s", long = "start")]
+    pub start: Option<String>,
+    #[clap(short = "e", long = "end")]
+    pub end: Option<String>,
+    #[clap(short = "d", long = "duration")]
+    pub duration: Option<String>,
+    #[clap(long = "mp4-mode")]
+    pub mp4_mode: Option<Mp4Mode>,
+    #[clap(long = "frag-duration")]
+    pub frag_duration: Option<i64>,
+    #[clap(long = "subtitle", number_of_values = 1)]
+    pub subtitles: Vec<SubtitleSpec>,
+    #[clap(long = "disposition", number_of_values = 1)]
+    pub dispositions: Vec<DispositionSpec>,
+    #[clap(long = "language", number_of_values = 1)]
+    pub languages: Vec<LanguageSpec>,
+    #[clap(long = "progress", default_value = "auto")]
+    pub progress: ProgressMode,
+    #[clap(flatten)]
+    pub input_format: InputFormat,
+    #[clap(flatten)]
+    pub output_format: OutputFormat,
 }
 
 #[derive(Clap)]
@@ -40,6 +97,27 @@ pub struct Transmux {
     pub input: String,
     #[clap(short = "o", long = "output")]
     pub output: String,
+    #[clap(
+        short = "m",
+        long = "map",
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    pub map: Vec<MapSpec>,
+    #[clap(long = "drop-data")]
+    pub drop_data: bool,
+    #[clap(long = "auto")]
+    pub auto: bool,
+    #[clap(long = "fix-timestamps")]
+    pub fix_timestamps: bool,
+    #[clap(long = "bsf", number_of_values = 1)]
+    pub bsf: Vec<BsfSpec>,
+    #[clap(long = "progress", default_value = "auto")]
+    pub progress: ProgressMode,
+    #[clap(flatten)]
+    pub input_format: InputFormat,
+    #[clap(flatten)]
+    pub output_format: OutputFormat,
 }
 
 #[derive(Clap)]
@@ -48,4 +126,438 @@ pub struct Transcode {
     pub input: String,
     #[clap(short = "o", long = "output")]
     pub output: String,
+    #[clap(long = "vcodec", default_value = "libx265")]
+    pub vcodec: String,
+    #[clap(long = "preset")]
+    pub preset: Option<String>,
+    #[clap(long = "crf")]
+    pub crf: Option<f64>,
+    #[clap(long = "bitrate")]
+    pub bitrate: Option<String>,
+    #[clap(long = "maxrate")]
+    pub maxrate: Option<String>,
+    #[clap(long = "bufsize")]
+    pub bufsize: Option<String>,
+    #[clap(long = "keyint")]
+    pub keyint: Option<i32>,
+    #[clap(long = "encoder-opt", number_of_values = 1)]
+    pub encoder_opts: Vec<KeyValue>,
+    #[clap(long = "acodec")]
+    pub acodec: Option<String>,
+    #[clap(long = "ab")]
+    pub ab: Option<String>,
+    #[clap(long = "ar")]
+    pub ar: Option<i32>,
+    #[clap(long = "ac")]
+    pub ac: Option<i32>,
+    #[clap(long = "mp4-mode")]
+    pub mp4_mode: Option<Mp4Mode>,
+    #[clap(long = "frag-duration")]
+    pub frag_duration: Option<i64>,
+    #[clap(long = "disposition", number_of_values = 1)]
+    pub dispositions: Vec<DispositionSpec>,
+    #[clap(long = "language", number_of_values = 1)]
+    pub languages: Vec<LanguageSpec>,
+    #[clap(long = "progress", default_value = "auto")]
+    pub progress: ProgressMode,
+    #[clap(flatten)]
+    pub input_format: InputFormat,
+    #[clap(flatten)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Clap)]
+pub struct ContactSheet {
+    #[clap(short = "i", long = "input")]
+    pub input: String,
+    #[clap(short = "o", long = "output")]
+    pub output: String,
+    #[clap(long = "rows", default_value = "4")]
+    pub rows: i32,
+    #[clap(long = "columns", default_value = "4")]
+    pub columns: i32,
+    #[clap(long = "width", default_value = "320")]
+    pub width: i32,
+    #[clap(long = "padding", default_value = "8")]
+    pub padding: i32,
+    #[clap(long = "no-timestamps")]
+    pub no_timestamps: bool,
+}
+
+#[derive(Clap)]
+pub struct Scenes {
+    #[clap(short = "i", long = "input")]
+    pub input: String,
+    #[clap(short = "o", long = "output")]
+    pub output: Option<String>,
+    #[clap(short = "t", long = "threshold", default_value = "0.3")]
+    pub threshold: f64,
+    #[clap(long = "min-scene-length", default_value = "1.0")]
+    pub min_scene_length: f64,
+    #[clap(short = "f", long = "format", default_value = "json")]
+    pub format: ReportFormat,
+}
+
+#[derive(Clap)]
+pub struct FrameHash {
+    #[clap(short = "i", long = "input")]
+    pub input: String,
+    #[clap(short = "o", long = "output")]
+    pub output: Option<String>,
+    #[clap(short = "s", long = "stream", number_of_values = 1)]
+    pub streams: Vec<usize>,
+    #[clap(short = "c", long = "compare")]
+    pub compare: Option<String>,
+}
+
+#[derive(Clap)]
+pub struct ImagesToVideo {
+    #[clap(short = "i", long = "input")]
+    pub input: String,
+    #[clap(short = "o", long = "output")]
+    pub output: String,
+    #[clap(short = "r", long = "framerate", default_value = "25")]
+    pub framerate: String,
+    #[clap(long = "vcodec", default_value = "libx264")]
+    pub vcodec: String,
+    #[clap(long = "pix-fmt")]
+    pub pix_fmt: Option<String>,
+    #[clap(flatten)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Clap)]
+pub struct Qc {
+    #[clap(short = "i", long = "input")]
+    pub input: String,
+    #[clap(short = "o", long = "output")]
+    pub output: Option<String>,
+    #[clap(long = "black-threshold", default_value = "32")]
+    pub black_threshold: u8,
+    #[clap(long = "black-ratio", default_value = "0.98")]
+    pub black_ratio: f64,
+    #[clap(long = "black-min-duration", default_value = "2.0")]
+    pub black_min_duration: f64,
+    #[clap(long = "freeze-threshold", default_value = "0.002")]
+    pub freeze_threshold: f64,
+    #[clap(long = "freeze-min-duration", default_value = "2.0")]
+    pub freeze_min_duration: f64,
+    #[clap(long = "frame-stats")]
+    pub frame_stats: bool,
+}
+
+#[derive(Clap)]
+pub struct Waveform {
+    #[clap(short = "i", long = "input")]
+    pub input: String,
+    #[clap(short = "o", long = "output")]
+    pub output: String,
+    #[clap(short = "s", long = "stream")]
+    pub stream: Option<i32>,
+    #[clap(long = "width", default_value = "1800")]
+    pub width: i32,
+    #[clap(long = "height", default_value = "280")]
+    pub height: i32,
+    #[clap(long = "peaks")]
+    pub peaks: Option<String>,
+    #[clap(long = "spectrogram")]
+    pub spectrogram: Option<String>,
+    #[clap(long = "fft-size", default_value = "1024")]
+    pub fft_size: usize,
+}
+
+#[derive(Clap)]
+pub struct Cut {
+    #[clap(short = "i", long = "input")]
+    pub input: String,
+    #[clap(short = "o", long = "output")]
+    pub output: String,
+    #[clap(short = "s", long = "start", default_value = "0")]
+    pub start: String,
+    #[clap(short = "e", long = "end")]
+    pub end: Option<String>,
+    #[clap(short = "d", long = "duration")]
+    pub duration: Option<String>,
+    #[clap(flatten)]
+    pub input_format: InputFormat,
+    #[clap(flatten)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Clap)]
+pub struct Concat {
+    #[clap(short = "i", long = "input", number_of_values = 1, required = true)]
+    pub inputs: Vec<String>,
+    #[clap(short = "o", long = "output")]
+    pub output: String,
+    #[clap(long = "reencode")]
+    pub reencode: bool,
+    #[clap(flatten)]
+    pub input_format: InputFormat,
+    #[clap(flatten)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Clap)]
+pub struct Hls {
+    #[clap(short = "i", long = "input")]
+    pub input: String,
+    #[clap(short = "o", long = "output-dir")]
+    pub output_dir: String,
+    #[clap(long = "playlist", default_value = "index.m3u8")]
+    pub playlist: String,
+    #[clap(short = "t", long = "segment-duration", default_value = "6")]
+    pub segment_duration: f64,
+    #[clap(long = "segment-type", default_value = "mpegts")]
+    pub segment_type: SegmentType,
+    #[clap(long = "playlist-type", default_value = "vod")]
+    pub playlist_type: PlaylistType,
+    #[clap(flatten)]
+    pub input_format: InputFormat,
+    #[clap(long = "muxer-opt", number_of_values = 1)]
+    pub muxer_opts: Vec<KeyValue>,
+}
+
+#[derive(Clap)]
+pub struct Dash {
+    #[clap(short = "i", long = "input")]
+    pub input: String,
+    #[clap(short = "o", long = "output-dir")]
+    pub output_dir: String,
+    #[clap(long = "manifest", default_value = "manifest.mpd")]
+    pub manifest: String,
+    #[clap(short = "t", long = "segment-duration", default_value = "4")]
+    pub segment_duration: f64,
+    #[clap(flatten)]
+    pub input_format: InputFormat,
+    #[clap(long = "muxer-opt", number_of_values = 1)]
+    pub muxer_opts: Vec<KeyValue>,
+}
+
+#[derive(Clap)]
+pub struct Split {
+    #[clap(short = "i", long = "input")]
+    pub input: String,
+    #[clap(short = "o", long = "output")]
+    pub output: String,
+    #[clap(short = "d", long = "segment-duration")]
+    pub segment_duration: Option<f64>,
+    // approximate, a part can go over by up to a GOP and the trailer
+    #[clap(short = "s", long = "max-size", parse(try_from_str = utils::parse_size))]
+    pub max_size: Option<u64>,
+    #[clap(long = "manifest")]
+    pub manifest: Option<String>,
+    #[clap(flatten)]
+    pub input_format: InputFormat,
+    #[clap(flatten)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Clap)]
+pub struct Mux {
+    #[clap(short = "i", long = "input", number_of_values = 1, required = true)]
+    pub inputs: Vec<String>,
+    #[clap(short = "o", long = "output")]
+    pub output: String,
+    #[clap(
+        short = "m",
+        long = "map",
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    pub map: Vec<InputMapSpec>,
+    #[clap(long = "offset", number_of_values = 1, allow_hyphen_values = true)]
+    pub offsets: Vec<InputOffset>,
+    #[clap(flatten)]
+    pub input_format: InputFormat,
+    #[clap(flatten)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Clap)]
+pub struct Extract {
+    #[clap(short = "i", long = "input")]
+    pub input: String,
+    #[clap(short = "s", long = "stream")]
+    pub stream: usize,
+    #[clap(short = "o", long = "output")]
+    pub output: Option<String>,
+    #[clap(flatten)]
+    pub input_format: InputFormat,
+    #[clap(flatten)]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Clap)]
+pub struct InputFormat {
+    #[clap(long = "input-format")]
+    pub format: Option<String>,
+    #[clap(long = "demuxer-opt", number_of_values = 1)]
+    pub demuxer_opts: Vec<KeyValue>,
+}
+
+impl InputFormat {
+    pub fn options(&self) -> Vec<(String, String)> {
+        key_values(&self.demuxer_opts)
+    }
+}
+
+#[derive(Clap)]
+pub struct OutputFormat {
+    #[clap(short = "f", long = "format")]
+    pub format: Option<String>,
+    #[clap(long = "muxer-opt", number_of_values = 1)]
+    pub muxer_opts: Vec<KeyValue>,
+}
+
+impl OutputFormat {
+    pub fn options(&self) -> Vec<(String, String)> {
+        key_values(&self.muxer_opts)
+    }
+}
+
+// `--muxer-opt` / `--demuxer-opt` entry, e.g. `movflags=+faststart`
+pub struct KeyValue {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for KeyValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+
+        match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) if !key.is_empty() => Ok(KeyValue {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(format!("expected key=value, got '{}'", s)),
+        }
+    }
+}
+
+pub fn key_values(entries: &[KeyValue]) -> Vec<(String, String)> {
+    entries
+        .iter()
+        .map(|entry| (entry.key.clone(), entry.value.clone()))
+        .collect()
+}
+
+// `--offset` entry, e.g. `1:-0.120` moves input 1 120ms earlier
+pub struct InputOffset {
+    pub input: usize,
+    pub offset: String,
+}
+
+impl FromStr for InputOffset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+
+        let input = parts
+            .next()
+            .and_then(|input| input.parse().ok())
+            .ok_or_else(|| format!("invalid input index in '{}'", s))?;
+
+        let offset = parts
+            .next()
+            .ok_or_else(|| format!("missing offset in '{}'", s))?
+            .to_string();
+
+        Ok(InputOffset { input, offset })
+    }
+}
+
+// `--subtitle` entry, e.g. `subs.srt:lang=eng:default` or `signs.ass:forced`
+pub struct SubtitleSpec {
+    pub path: String,
+    pub language: Option<String>,
+    pub default: bool,
+    pub forced: bool,
+}
+
+impl FromStr for SubtitleSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+
+        let mut spec = SubtitleSpec {
+            path: parts.next().unwrap_or("").to_string(),
+            language: None,
+            default: false,
+            forced: false,
+        };
+
+        if spec.path.is_empty() {
+            return Err(format!("missing subtitle file in '{}'", s));
+        }
+
+        for option in parts {
+            match option {
+                "default" => spec.default = true,
+                "forced" => spec.forced = true,
+                _ if option.starts_with("lang=") => {
+                    spec.language = Some(option["lang=".len()..].to_string())
+                }
+                _ => return Err(format!("unknown subtitle option '{}'", option)),
+            }
+        }
+
+        Ok(spec)
+    }
+}
+
+pub enum SegmentType {
+    MpegTs,
+    Fmp4,
+}
+
+impl FromStr for SegmentType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mpegts" => Ok(SegmentType::MpegTs),
+            "fmp4" => Ok(SegmentType::Fmp4),
+            x => Err(format!("unknown segment type '{}'", x)),
+        }
+    }
+}
+
+pub enum PlaylistType {
+    Vod,
+    Event,
+}
+
+impl FromStr for PlaylistType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vod" => Ok(PlaylistType::Vod),
+            "event" => Ok(PlaylistType::Event),
+            x => Err(format!("unknown playlist type '{}'", x)),
+        }
+    }
+}
+
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            x => Err(format!("unknown report format '{}'", x)),
+        }
+    }
 }